@@ -0,0 +1,132 @@
+// Compares parsing into the boxed Expr tree against parsing into an ExprArena: time to parse,
+// number of allocations, and memory. Run with `cargo bench --bench arena`.
+//
+// The token stream is generated directly rather than scanned, so only the parser is measured.
+// Memory includes the tokens the parser is given, which both free once parsed (apart from the
+// clones the boxed tree keeps in its nodes), so "peak" is the most memory used while parsing and
+// "retained" what is still held by the result.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use yarli::arena::ExprArena;
+use yarli::lexer::{LiteralValue, Span, Token, TokenType};
+use yarli::parser::Parser;
+
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(allocated, Ordering::Relaxed);
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const RUNS: usize = 10;
+
+// Each term is `(-N + N * N > N ? N : N)`. Terms are joined by commas.
+fn generate_tokens(terms: usize) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+    let mut push = |token_type: TokenType, lexeme: String, literal: Option<LiteralValue>| {
+        let span = Span::new(offset, offset + lexeme.len());
+        offset += lexeme.len() + 1;
+        tokens.push(Token { token_type, lexeme, literal, line: 1, span });
+    };
+    for term in 0..terms {
+        if term > 0 {
+            push(TokenType::COMMA, String::from(","), None);
+        }
+        let number = |push: &mut dyn FnMut(TokenType, String, Option<LiteralValue>), n: usize| {
+            push(TokenType::NUMBER, n.to_string(), Some(LiteralValue::Number(n as f64)));
+        };
+        push(TokenType::LEFT_PAREN, String::from("("), None);
+        push(TokenType::MINUS, String::from("-"), None);
+        number(&mut push, term);
+        push(TokenType::PLUS, String::from("+"), None);
+        number(&mut push, term + 1);
+        push(TokenType::STAR, String::from("*"), None);
+        number(&mut push, term + 2);
+        push(TokenType::GREATER, String::from(">"), None);
+        number(&mut push, term + 3);
+        push(TokenType::QUESTION, String::from("?"), None);
+        number(&mut push, term + 4);
+        push(TokenType::COLON, String::from(":"), None);
+        number(&mut push, term + 5);
+        push(TokenType::RIGHT_PAREN, String::from(")"), None);
+    }
+    push(TokenType::EOF, String::new(), None);
+    tokens
+}
+
+struct Measurement {
+    median: Duration,
+    allocations: usize,
+    peak: usize,
+    retained: usize,
+}
+
+// `parse` gets a fresh copy of the tokens on every run, and what it returns is kept alive until
+// after the memory is measured.
+fn measure<T>(tokens: &[Token], parse: impl Fn(Vec<Token>) -> T) -> Measurement {
+    let mut times = Vec::new();
+    let mut measurement = Measurement { median: Duration::ZERO, allocations: 0, peak: 0, retained: 0 };
+    for _ in 0..RUNS {
+        let allocated_before = ALLOCATED.load(Ordering::Relaxed);
+        let tokens = tokens.to_vec();
+        let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+        PEAK.store(ALLOCATED.load(Ordering::Relaxed), Ordering::Relaxed);
+        let start = Instant::now();
+        let tree = black_box(parse(tokens));
+        times.push(start.elapsed());
+        measurement.allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+        measurement.peak = PEAK.load(Ordering::Relaxed) - allocated_before;
+        measurement.retained = ALLOCATED.load(Ordering::Relaxed) - allocated_before;
+        drop(tree);
+    }
+    times.sort();
+    measurement.median = times[RUNS / 2];
+    measurement
+}
+
+fn mebibytes(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+fn report(name: &str, measurement: &Measurement) {
+    println!(
+        "{name:>6}: {:>10.2?} to parse, {:>7} allocations, {:>7.2} MiB peak, {:>7.2} MiB retained",
+        measurement.median,
+        measurement.allocations,
+        mebibytes(measurement.peak),
+        mebibytes(measurement.retained),
+    );
+}
+
+fn main() {
+    for terms in [1_000, 10_000, 50_000] {
+        let tokens = generate_tokens(terms);
+        let boxed = measure(&tokens, |tokens| Parser::new(tokens).parse().unwrap());
+        let arena = measure(&tokens, |tokens| ExprArena::parse(tokens).ok().unwrap());
+        let nodes = ExprArena::parse(tokens.clone()).ok().unwrap().len();
+        println!("{nodes} nodes, {} tokens", tokens.len());
+        report("boxed", &boxed);
+        report("arena", &arena);
+        println!();
+    }
+}
@@ -0,0 +1,334 @@
+use std::cell::RefCell;
+use std::ops::Index;
+use crate::lexer::{Literal, Span, Token, TokenType};
+use crate::parser::{Expr, ExprKind, NodeId, ParseError, Parser, TreeBuilder};
+
+// An alternative representation of the expression tree for very large programs. Instead of one
+// allocation per node, all nodes live in a single vector and refer to their children by NodeId,
+// which is their index in that vector. The tokens aren't kept: operators only keep their type,
+// line and span, and literals their value, each in a side vector of their own.
+//
+// Nodes are numbered in the same order as the boxed tree's, so the ids (and any side table keyed
+// by them) are interchangeable between both representations. Children always come before their
+// parents. See benches/arena.rs for how both compare.
+pub struct ExprArena {
+    nodes: Vec<ArenaExpr>,
+    // The elements of all the lists and maps, one after the other
+    elements: Vec<NodeId>,
+    operators: Vec<ArenaOperator>,
+    literals: Vec<Literal>,
+    // The unexpected tokens of all the error nodes, which only parse_tolerant() builds
+    error_tokens: Vec<Token>,
+    // The spans of the ']' of all the index assignments
+    closings: Vec<Span>,
+    root: NodeId,
+}
+
+// An operator token, without its lexeme, which the type gives anyway.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArenaOperator {
+    pub token_type: TokenType,
+    pub line: usize,
+    pub span: Span,
+}
+
+impl ArenaOperator {
+    fn to_token(self) -> Token {
+        let lexeme = self.token_type.lexeme().unwrap_or_else(|| panic!("not an operator: {}", self.token_type));
+        Token { token_type: self.token_type, lexeme: String::from(lexeme), literal: None, line: self.line, span: self.span }
+    }
+}
+
+// Index into the operators of the arena.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OperatorIndex(pub u32);
+
+// Index into the literal values of the arena.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LiteralIndex(pub u32);
+
+// Index into the closing brackets of the arena.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ClosingIndex(pub u32);
+
+// Where the elements of a list or map are. Use ExprArena::elements() to get them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Elements {
+    start: u32,
+    len: u32,
+}
+
+// Where the tokens of an error node are. Use ExprArena::error_tokens() to get them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ErrorTokens {
+    start: u32,
+    len: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ArenaExpr {
+    pub span: Span,
+    pub kind: ArenaExprKind,
+}
+
+// Mirrors parser::ExprKind.
+#[derive(Clone, Copy, Debug)]
+pub enum ArenaExprKind {
+    Binary { left: NodeId, operator: OperatorIndex, right: NodeId },
+    Conditional { condition: NodeId, then_branch: NodeId, else_branch: NodeId },
+    Error { expression: Option<NodeId>, tokens: ErrorTokens },
+    Grouping { expression: NodeId },
+    Index { object: NodeId, index: NodeId },
+    IndexAssign { object: NodeId, index: NodeId, closing: ClosingIndex, operator: OperatorIndex, value: NodeId },
+    List { elements: Elements },
+    // The keys and values, one after the other, starting with a key
+    Map { entries: Elements },
+    Literal { value: LiteralIndex },
+    Missing,
+    Postfix { left: NodeId, operator: OperatorIndex },
+    Unary { operator: OperatorIndex, right: NodeId },
+}
+
+impl ExprArena {
+    pub fn parse(tokens: Vec<Token>) -> Result<ExprArena, Vec<ParseError>> {
+        let parser = Parser::with_builder(tokens, ArenaBuilder::default());
+        let root = parser.parse();
+        let (_, builder, errors) = parser.into_parts();
+        match root {
+            Some(root) => Ok(ExprArena::new(builder, root)),
+            None => Err(errors),
+        }
+    }
+
+    // Same as Parser::parse_tolerant(): there is always a tree, and the errors come with it.
+    pub fn parse_tolerant(tokens: Vec<Token>) -> (ExprArena, Vec<ParseError>) {
+        let parser = Parser::with_builder(tokens, ArenaBuilder::default());
+        let root = parser.parse_tolerant();
+        let (_, builder, errors) = parser.into_parts();
+        (ExprArena::new(builder, root), errors)
+    }
+
+    fn new(builder: ArenaBuilder, root: NodeId) -> ExprArena {
+        ExprArena {
+            nodes: builder.nodes.into_inner(),
+            elements: builder.elements.into_inner(),
+            operators: builder.operators.into_inner(),
+            literals: builder.literals.into_inner(),
+            error_tokens: builder.error_tokens.into_inner(),
+            closings: builder.closings.into_inner(),
+            root,
+        }
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn operator(&self, index: OperatorIndex) -> ArenaOperator {
+        self.operators[index.0 as usize]
+    }
+
+    pub fn literal(&self, index: LiteralIndex) -> &Literal {
+        &self.literals[index.0 as usize]
+    }
+
+    pub fn closing(&self, index: ClosingIndex) -> Span {
+        self.closings[index.0 as usize]
+    }
+
+    pub fn error_tokens(&self, tokens: ErrorTokens) -> &[Token] {
+        &self.error_tokens[tokens.start as usize..(tokens.start + tokens.len) as usize]
+    }
+
+    pub fn elements(&self, elements: Elements) -> &[NodeId] {
+        &self.elements[elements.start as usize..(elements.start + elements.len) as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // All nodes, children before parents.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &ArenaExpr)> {
+        self.nodes.iter().enumerate().map(|(i, node)| (NodeId(i as u32), node))
+    }
+
+    // Builds the equivalent boxed tree, with the same ids, e.g. to use the printers.
+    pub fn to_expr(&self) -> Expr {
+        self.build_expr(self.root)
+    }
+
+    fn build_expr(&self, id: NodeId) -> Expr {
+        let node = &self[id];
+        let kind = match node.kind {
+            ArenaExprKind::Binary { left, operator, right } => ExprKind::Binary {
+                left: Box::new(self.build_expr(left)),
+                operator: self.operator(operator).to_token(),
+                right: Box::new(self.build_expr(right)),
+            },
+            ArenaExprKind::Conditional { condition, then_branch, else_branch } => ExprKind::Conditional {
+                condition: Box::new(self.build_expr(condition)),
+                then_branch: Box::new(self.build_expr(then_branch)),
+                else_branch: Box::new(self.build_expr(else_branch)),
+            },
+            ArenaExprKind::Error { expression, tokens } => ExprKind::Error {
+                expression: expression.map(|expression| Box::new(self.build_expr(expression))),
+                tokens: self.error_tokens(tokens).to_vec(),
+            },
+            ArenaExprKind::Grouping { expression } => ExprKind::Grouping { expression: Box::new(self.build_expr(expression)) },
+            ArenaExprKind::Index { object, index } => ExprKind::Index {
+                object: Box::new(self.build_expr(object)),
+                index: Box::new(self.build_expr(index)),
+            },
+            ArenaExprKind::IndexAssign { object, index, closing, operator, value } => ExprKind::IndexAssign {
+                object: Box::new(self.build_expr(object)),
+                index: Box::new(self.build_expr(index)),
+                closing: self.closing(closing),
+                operator: self.operator(operator).to_token(),
+                value: Box::new(self.build_expr(value)),
+            },
+            ArenaExprKind::List { elements } => ExprKind::List {
+                elements: self.elements(elements).iter().map(|element| self.build_expr(*element)).collect(),
+            },
+            ArenaExprKind::Literal { value } => ExprKind::Literal { value: self.literal(value).clone() },
+            ArenaExprKind::Map { entries } => ExprKind::Map {
+                entries: self
+                    .elements(entries)
+                    .chunks(2)
+                    .map(|entry| (self.build_expr(entry[0]), self.build_expr(entry[1])))
+                    .collect(),
+            },
+            ArenaExprKind::Missing => ExprKind::Missing,
+            ArenaExprKind::Postfix { left, operator } => ExprKind::Postfix {
+                left: Box::new(self.build_expr(left)),
+                operator: self.operator(operator).to_token(),
+            },
+            ArenaExprKind::Unary { operator, right } => ExprKind::Unary {
+                operator: self.operator(operator).to_token(),
+                right: Box::new(self.build_expr(right)),
+            },
+        };
+        Expr { id, span: node.span, kind }
+    }
+}
+
+impl Index<NodeId> for ExprArena {
+    type Output = ArenaExpr;
+
+    fn index(&self, id: NodeId) -> &ArenaExpr {
+        &self.nodes[id.0 as usize]
+    }
+}
+
+#[derive(Default)]
+struct ArenaBuilder {
+    nodes: RefCell<Vec<ArenaExpr>>,
+    elements: RefCell<Vec<NodeId>>,
+    operators: RefCell<Vec<ArenaOperator>>,
+    literals: RefCell<Vec<Literal>>,
+    error_tokens: RefCell<Vec<Token>>,
+    closings: RefCell<Vec<Span>>,
+}
+
+impl ArenaBuilder {
+    fn push(&self, span: Span, kind: ArenaExprKind) -> NodeId {
+        let mut nodes = self.nodes.borrow_mut();
+        nodes.push(ArenaExpr { span, kind });
+        NodeId((nodes.len() - 1) as u32)
+    }
+
+    fn span(&self, id: NodeId) -> Span {
+        self.nodes.borrow()[id.0 as usize].span
+    }
+
+    fn add_elements(&self, elements: Vec<NodeId>) -> Elements {
+        let start = self.elements.borrow().len() as u32;
+        self.elements.borrow_mut().extend(&elements);
+        Elements { start, len: elements.len() as u32 }
+    }
+
+    fn add_operator(&self, operator: &Token) -> OperatorIndex {
+        let mut operators = self.operators.borrow_mut();
+        operators.push(ArenaOperator { token_type: operator.token_type, line: operator.line, span: operator.span });
+        OperatorIndex((operators.len() - 1) as u32)
+    }
+}
+
+impl TreeBuilder for ArenaBuilder {
+    type Node = NodeId;
+
+    fn binary(&self, left: NodeId, operator: &Token, _operator_index: usize, right: NodeId) -> NodeId {
+        let span = self.span(left).to(self.span(right));
+        let operator = self.add_operator(operator);
+        self.push(span, ArenaExprKind::Binary { left, operator, right })
+    }
+
+    fn conditional(&self, condition: NodeId, then_branch: NodeId, else_branch: NodeId) -> NodeId {
+        let span = self.span(condition).to(self.span(else_branch));
+        self.push(span, ArenaExprKind::Conditional { condition, then_branch, else_branch })
+    }
+
+    fn error(&self, expression: Option<NodeId>, tokens: &[Token], _first_token_index: usize) -> NodeId {
+        let mut span = tokens[0].span.to(tokens[tokens.len() - 1].span);
+        if let Some(expression) = expression {
+            span = self.span(expression).to(span);
+        }
+        let start = self.error_tokens.borrow().len() as u32;
+        self.error_tokens.borrow_mut().extend_from_slice(tokens);
+        let tokens = ErrorTokens { start, len: tokens.len() as u32 };
+        self.push(span, ArenaExprKind::Error { expression, tokens })
+    }
+
+    fn grouping(&self, span: Span, expression: NodeId) -> NodeId {
+        self.push(span, ArenaExprKind::Grouping { expression })
+    }
+
+    fn index(&self, object: NodeId, index: NodeId, closing: Span) -> NodeId {
+        let span = self.span(object).to(closing);
+        self.push(span, ArenaExprKind::Index { object, index })
+    }
+
+    fn index_assign(&self, object: NodeId, index: NodeId, closing: Span, operator: &Token, _operator_index: usize, value: NodeId) -> NodeId {
+        let span = self.span(object).to(self.span(value));
+        let operator = self.add_operator(operator);
+        let closing_index = ClosingIndex(self.closings.borrow().len() as u32);
+        self.closings.borrow_mut().push(closing);
+        self.push(span, ArenaExprKind::IndexAssign { object, index, closing: closing_index, operator, value })
+    }
+
+    fn list(&self, span: Span, elements: Vec<NodeId>) -> NodeId {
+        let elements = self.add_elements(elements);
+        self.push(span, ArenaExprKind::List { elements })
+    }
+
+    fn map(&self, span: Span, entries: Vec<(NodeId, NodeId)>) -> NodeId {
+        let entries = self.add_elements(entries.into_iter().flat_map(|(key, value)| [key, value]).collect());
+        self.push(span, ArenaExprKind::Map { entries })
+    }
+
+    fn literal(&self, token: &Token, _token_index: usize) -> NodeId {
+        let value = LiteralIndex(self.literals.borrow().len() as u32);
+        self.literals.borrow_mut().push(token.literal.clone());
+        self.push(token.span, ArenaExprKind::Literal { value })
+    }
+
+    fn missing(&self, position: usize) -> NodeId {
+        self.push(Span::new(position, position), ArenaExprKind::Missing)
+    }
+
+    fn postfix(&self, left: NodeId, operator: &Token, _operator_index: usize) -> NodeId {
+        let span = self.span(left).to(operator.span);
+        let operator = self.add_operator(operator);
+        self.push(span, ArenaExprKind::Postfix { left, operator })
+    }
+
+    fn unary(&self, operator: &Token, _operator_index: usize, right: NodeId) -> NodeId {
+        let span = operator.span.to(self.span(right));
+        let operator = self.add_operator(operator);
+        self.push(span, ArenaExprKind::Unary { operator, right })
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt;
+use crate::lexer::{LiteralValue, Span, Token, TokenType};
+use crate::parser::{Expr, ExprKind};
+
+// A static type checker, run by `yarli check`. It infers the type of every expression and reports
+// the operations that are bound to fail at runtime, like `"a" - 1`, before anything runs.
+//
+// The typing is gradual: whatever can't be known statically, like a list element, is Any, and
+// Any goes with everything. So code the checker knows nothing about is never rejected, and the
+// rules below only apply where both sides are known. They are the ones of the book's interpreter:
+// arithmetic on numbers, `+` on two numbers or two strings, and comparisons on numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    Any,
+    Bool,
+    List,
+    Map,
+    Nil,
+    Number,
+    Range,
+    String,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[derive(Debug)]
+pub struct TypeError {
+    pub line: usize,
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[line {}] Error{}: {}", self.line, self.location, self.message)
+    }
+}
+
+// Returns the type of `expr`, which was parsed from `source`, along with the errors found in it.
+pub fn check(source: &str, expr: &Expr) -> (Type, Vec<TypeError>) {
+    let mut checker = Checker { source, errors: Vec::new() };
+    let expr_type = checker.expr(expr);
+    (expr_type, checker.errors)
+}
+
+struct Checker<'a> {
+    source: &'a str,
+    errors: Vec<TypeError>,
+}
+
+impl Checker<'_> {
+    fn expr(&mut self, expr: &Expr) -> Type {
+        match &expr.kind {
+            ExprKind::Binary { left, operator, right } => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                self.binary(operator, operator.token_type, left, right)
+            }
+            // Either branch may be taken.
+            ExprKind::Conditional { condition, then_branch, else_branch } => {
+                self.expr(condition);
+                let then_type = self.expr(then_branch);
+                let else_type = self.expr(else_branch);
+                if then_type == else_type { then_type } else { Type::Any }
+            }
+            // Only parse_tolerant() builds those, and there is nothing to check in them.
+            ExprKind::Error { .. } | ExprKind::Missing => Type::Any,
+            ExprKind::Grouping { expression } => self.expr(expression),
+            ExprKind::Index { object, index } => self.index(object, index),
+            ExprKind::IndexAssign { object, index, operator, value, .. } => {
+                let element = self.index(object, index);
+                let value = self.expr(value);
+                match compound_operator(operator.token_type) {
+                    Some(token_type) => self.binary(operator, token_type, element, value),
+                    None => value,
+                }
+            }
+            ExprKind::List { elements } => {
+                for element in elements {
+                    self.expr(element);
+                }
+                Type::List
+            }
+            ExprKind::Literal { value } => match value {
+                Some(LiteralValue::Bool(_)) => Type::Bool,
+                Some(LiteralValue::Number(_)) => Type::Number,
+                Some(LiteralValue::String(_)) => Type::String,
+                None => Type::Nil,
+            },
+            ExprKind::Map { entries } => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+                Type::Map
+            }
+            ExprKind::Postfix { left, operator } => {
+                let operand = self.expr(left);
+                self.unary(operator, operand)
+            }
+            ExprKind::Unary { operator, right } => {
+                let operand = self.expr(right);
+                self.unary(operator, operand)
+            }
+        }
+    }
+
+    // `token_type` is the operation to check, which for compound assignments isn't the operator's
+    // own: `a[0] -= 1` subtracts.
+    fn binary(&mut self, operator: &Token, token_type: TokenType, left: Type, right: Type) -> Type {
+        match token_type {
+            TokenType::COMMA => right,
+            TokenType::EQUAL_EQUAL | TokenType::BANG_EQUAL => Type::Bool,
+            TokenType::GREATER | TokenType::GREATER_EQUAL | TokenType::LESS | TokenType::LESS_EQUAL => {
+                self.numbers(operator, left, right);
+                Type::Bool
+            }
+            TokenType::DOT_DOT | TokenType::DOT_DOT_EQUAL => {
+                self.numbers(operator, left, right);
+                Type::Range
+            }
+            // Whichever side is known tells the other one, and the result.
+            TokenType::PLUS => match (left, right) {
+                (Type::Any, Type::Any) => Type::Any,
+                (Type::Number | Type::String, Type::Any) => left,
+                (Type::Any, Type::Number | Type::String) => right,
+                (Type::Number, Type::Number) | (Type::String, Type::String) => left,
+                _ => {
+                    self.error(operator, "Operands must be two numbers or two strings.");
+                    Type::Any
+                }
+            },
+            _ => {
+                self.numbers(operator, left, right);
+                Type::Number
+            }
+        }
+    }
+
+    fn numbers(&mut self, operator: &Token, left: Type, right: Type) {
+        if !is_number(left) || !is_number(right) {
+            self.error(operator, "Operands must be numbers.");
+        }
+    }
+
+    fn unary(&mut self, operator: &Token, operand: Type) -> Type {
+        if operator.token_type == TokenType::BANG {
+            return Type::Bool;
+        }
+        if !is_number(operand) {
+            self.error(operator, "Operand must be a number.");
+        }
+        Type::Number
+    }
+
+    // The type of the element, which is never known.
+    fn index(&mut self, object: &Expr, index: &Expr) -> Type {
+        let object_type = self.expr(object);
+        let index_type = self.expr(index);
+        match object_type {
+            Type::Any | Type::Map => (),
+            Type::List => {
+                if !is_number(index_type) {
+                    self.error_at(index.span, "List index must be a number.");
+                }
+            }
+            _ => self.error_at(object.span, "Only lists and maps can be indexed."),
+        }
+        Type::Any
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        let location = format!(" at '{}'", token.lexeme);
+        self.errors.push(TypeError { line: token.line, location, message: String::from(message) });
+    }
+
+    // For errors about a whole expression rather than an operator. Only its first line is shown,
+    // which is enough to find it and keeps the error on one line.
+    fn error_at(&mut self, span: Span, message: &str) {
+        let line = self.source[..span.start].matches('\n').count() + 1;
+        let text = &self.source[span.start..span.end];
+        let location = format!(" at '{}'", text.lines().next().unwrap_or(text));
+        self.errors.push(TypeError { line, location, message: String::from(message) });
+    }
+}
+
+fn is_number(operand: Type) -> bool {
+    matches!(operand, Type::Number | Type::Any)
+}
+
+// The operation a compound assignment does, or None for a plain one.
+fn compound_operator(token_type: TokenType) -> Option<TokenType> {
+    match token_type {
+        TokenType::PLUS_EQUAL => Some(TokenType::PLUS),
+        TokenType::MINUS_EQUAL => Some(TokenType::MINUS),
+        TokenType::STAR_EQUAL => Some(TokenType::STAR),
+        TokenType::SLASH_EQUAL => Some(TokenType::SLASH),
+        TokenType::PERCENT_EQUAL => Some(TokenType::PERCENT),
+        _ => None,
+    }
+}
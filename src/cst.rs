@@ -0,0 +1,626 @@
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+use crate::lexer::{Literal, LiteralValue, Span, Token, TokenType};
+use crate::parser::{Expr, ExprKind, ParseError, Parser};
+
+// A concrete syntax tree, for tools that rewrite code and must leave everything they don't touch
+// exactly as it was. Unlike the Expr tree, it keeps every token, including whitespace, comments
+// and whatever could not be scanned, so printing it gives back the source byte for byte.
+//
+// It comes in two layers, like the green and red trees of Roslyn and rust-analyzer:
+// - Green nodes only know their kind, their children and their width. They are immutable and
+//   shared through Rc, so an edit rebuilds the path from the edited node up to the root and
+//   reuses everything else (see SyntaxNode::replace_with()).
+// - Syntax nodes are built on demand on top of the green ones, and add what depends on where a
+//   node is: its parent and its offset in the source.
+// The typed view at the end of this file (SourceFile, AstExpr and friends) gives the nodes named
+// accessors, like BinaryExpr::right().
+//
+// Every token belongs to exactly one node. Trivia goes to the innermost node whose span contains
+// it; trivia before or after the expression goes to the SourceFile node, along with the final
+// EOF token.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    SourceFile,
+    Binary,
+    Conditional,
+    Error,
+    Grouping,
+    Index,
+    IndexAssign,
+    List,
+    Literal,
+    Map,
+    Missing,
+    Postfix,
+    Unary,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct GreenToken {
+    kind: TokenType,
+    text: String,
+}
+
+impl GreenToken {
+    pub fn new(kind: TokenType, text: String) -> Self {
+        GreenToken { kind, text }
+    }
+
+    pub fn kind(&self) -> TokenType {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    fn width(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.width,
+            GreenElement::Token(token) => token.text.len(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct GreenNode {
+    kind: NodeKind,
+    // The length of the text of the node, in bytes
+    width: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: NodeKind, children: Vec<GreenElement>) -> Self {
+        let width = children.iter().map(GreenElement::width).sum();
+        GreenNode { kind, width, children }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+}
+
+// The source text of the node.
+impl fmt::Display for GreenNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => write!(f, "{node}")?,
+                GreenElement::Token(token) => write!(f, "{}", token.text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Scans nothing itself: `tokens` and `trivia` are what Scanner::scan_tokens_with_trivia() returns.
+// The parser runs in its tolerant mode, so there is always a tree, whatever the errors.
+pub fn parse(tokens: Vec<Token>, trivia: Vec<Token>) -> (SyntaxNode, Vec<ParseError>) {
+    let parser = Parser::new(tokens);
+    let expr = parser.parse_tolerant();
+    let (tokens, _, errors) = parser.into_parts();
+    let green = build(&expr, &tokens, &trivia);
+    (SyntaxNode::new_root(Rc::new(green)), errors)
+}
+
+// Builds the green tree of the SourceFile for `expr`, which must have been parsed from `tokens`.
+pub fn build(expr: &Expr, tokens: &[Token], trivia: &[Token]) -> GreenNode {
+    let mut all: Vec<&Token> = tokens.iter().chain(trivia).collect();
+    all.sort_by_key(|token| token.span.start);
+    let mut builder = GreenBuilder { tokens: all, next: 0 };
+
+    let mut children = Vec::new();
+    builder.tokens_before(expr.span.start, &mut children);
+    children.push(GreenElement::Node(Rc::new(builder.node(expr))));
+    // The rest, EOF included
+    builder.tokens_before(usize::MAX, &mut children);
+    GreenNode::new(NodeKind::SourceFile, children)
+}
+
+struct GreenBuilder<'a> {
+    // Tokens and trivia, in source order
+    tokens: Vec<&'a Token>,
+    // Everything before this one is in the tree already.
+    next: usize,
+}
+
+impl GreenBuilder<'_> {
+    // The node for `expr` gets its child nodes, and the tokens that are within its span but not
+    // within theirs.
+    fn node(&mut self, expr: &Expr) -> GreenNode {
+        let (kind, exprs): (NodeKind, Vec<&Expr>) = match &expr.kind {
+            ExprKind::Binary { left, right, .. } => (NodeKind::Binary, vec![left, right]),
+            ExprKind::Conditional { condition, then_branch, else_branch } =>
+                (NodeKind::Conditional, vec![condition, then_branch, else_branch]),
+            ExprKind::Error { expression, .. } => (NodeKind::Error, expression.iter().map(|e| e.as_ref()).collect()),
+            ExprKind::Grouping { expression } => (NodeKind::Grouping, vec![expression]),
+            ExprKind::Index { object, index } => (NodeKind::Index, vec![object, index]),
+            ExprKind::IndexAssign { object, index, value, .. } => (NodeKind::IndexAssign, vec![object, index, value]),
+            ExprKind::List { elements } => (NodeKind::List, elements.iter().collect()),
+            ExprKind::Map { entries } => (NodeKind::Map, entries.iter().flat_map(|(key, value)| [key, value]).collect()),
+            ExprKind::Literal { .. } => (NodeKind::Literal, vec![]),
+            ExprKind::Missing => (NodeKind::Missing, vec![]),
+            ExprKind::Postfix { left, .. } => (NodeKind::Postfix, vec![left]),
+            ExprKind::Unary { right, .. } => (NodeKind::Unary, vec![right]),
+        };
+
+        let mut children = Vec::new();
+        for child in exprs {
+            self.tokens_before(child.span.start, &mut children);
+            children.push(GreenElement::Node(Rc::new(self.node(child))));
+        }
+        self.tokens_before(expr.span.end, &mut children);
+        GreenNode::new(kind, children)
+    }
+
+    // A Missing node gets in before a token starting at the same position, since its parent's
+    // span ends there.
+    fn tokens_before(&mut self, position: usize, children: &mut Vec<GreenElement>) {
+        while let Some(token) = self.tokens.get(self.next) {
+            if token.span.start >= position {
+                break;
+            }
+            children.push(GreenElement::Token(Rc::new(GreenToken::new(token.token_type, token.lexeme.clone()))));
+            self.next += 1;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    // The parent, and our index among its children
+    parent: Option<(SyntaxNode, usize)>,
+    offset: usize,
+}
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    parent: SyntaxNode,
+    // Our index among the parent's children
+    index: usize,
+    offset: usize,
+}
+
+#[derive(Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Rc<GreenNode>) -> SyntaxNode {
+        SyntaxNode(Rc::new(NodeData { green, parent: None, offset: 0 }))
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.as_ref().map(|(parent, _)| parent.clone())
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.0.offset, self.0.offset + self.0.green.width)
+    }
+
+    pub fn text(&self) -> String {
+        self.0.green.to_string()
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut elements = Vec::new();
+        for (index, child) in self.0.green.children.iter().enumerate() {
+            elements.push(match child {
+                GreenElement::Node(green) => {
+                    let parent = Some((self.clone(), index));
+                    SyntaxElement::Node(SyntaxNode(Rc::new(NodeData { green: green.clone(), parent, offset })))
+                }
+                GreenElement::Token(green) =>
+                    SyntaxElement::Token(SyntaxToken { green: green.clone(), parent: self.clone(), index, offset }),
+            });
+            offset += child.width();
+        }
+        elements
+    }
+
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        let elements = self.children_with_tokens().into_iter();
+        elements.filter_map(|element| match element {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        }).collect()
+    }
+
+    // The tokens of this node and of all the nodes below it, trivia included, in source order.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    // The token with the byte at `offset` in it. There is none at the end of the source, where
+    // only the empty EOF token is.
+    pub fn token_at(&self, offset: usize) -> Option<SyntaxToken> {
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) if node.span().start <= offset && offset < node.span().end =>
+                    return node.token_at(offset),
+                SyntaxElement::Token(token) if token.span().start <= offset && offset < token.span().end =>
+                    return Some(token),
+                _ => (),
+            }
+        }
+        None
+    }
+
+    // Returns the green root of a tree where this node is replaced with `replacement`. Only the
+    // nodes on the way up to the root are rebuilt; everything else is shared with this tree.
+    pub fn replace_with(&self, replacement: Rc<GreenNode>) -> Rc<GreenNode> {
+        let mut green = replacement;
+        let mut node = self.clone();
+        while let Some((parent, index)) = node.0.parent.clone() {
+            let mut children = parent.0.green.children.clone();
+            children[index] = GreenElement::Node(green);
+            green = Rc::new(GreenNode::new(parent.kind(), children));
+            node = parent;
+        }
+        green
+    }
+}
+
+// Like Expr, shows the kind and the span: `Binary@0..5`.
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let span = self.span();
+        write!(f, "{:?}@{}..{}", self.kind(), span.start, span.end)
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> TokenType {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.offset, self.offset + self.green.text.len())
+    }
+
+    pub fn parent(&self) -> SyntaxNode {
+        self.parent.clone()
+    }
+
+    // Same as SyntaxNode::replace_with(), for a token.
+    pub fn replace_with(&self, replacement: GreenToken) -> Rc<GreenNode> {
+        let mut children = self.parent.0.green.children.clone();
+        children[self.index] = GreenElement::Token(Rc::new(replacement));
+        self.parent.replace_with(Rc::new(GreenNode::new(self.parent.kind(), children)))
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.kind(), TokenType::WHITESPACE | TokenType::COMMENT | TokenType::UNKNOWN)
+    }
+}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let span = self.span();
+        write!(f, "{:?}@{}..{} {:?}", self.kind(), span.start, span.end, self.text())
+    }
+}
+
+// The typed view. Each type wraps a SyntaxNode of the matching kind, and finds its parts among
+// the node's children. Parts may be absent, so the accessors return Options even where the
+// parser always puts something (a Missing node, for instance).
+
+pub struct SourceFile(SyntaxNode);
+
+impl SourceFile {
+    pub fn cast(node: SyntaxNode) -> Option<SourceFile> {
+        (node.kind() == NodeKind::SourceFile).then_some(SourceFile(node))
+    }
+
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    pub fn expression(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 0)
+    }
+}
+
+pub enum AstExpr {
+    Binary(BinaryExpr),
+    Conditional(ConditionalExpr),
+    Error(ErrorExpr),
+    Grouping(GroupingExpr),
+    Index(IndexExpr),
+    IndexAssign(IndexAssignExpr),
+    List(ListExpr),
+    Literal(LiteralExpr),
+    Map(MapExpr),
+    Missing(MissingExpr),
+    Postfix(PostfixExpr),
+    Unary(UnaryExpr),
+}
+
+pub struct BinaryExpr(SyntaxNode);
+pub struct ConditionalExpr(SyntaxNode);
+pub struct ErrorExpr(SyntaxNode);
+pub struct GroupingExpr(SyntaxNode);
+pub struct IndexExpr(SyntaxNode);
+pub struct IndexAssignExpr(SyntaxNode);
+pub struct ListExpr(SyntaxNode);
+pub struct LiteralExpr(SyntaxNode);
+pub struct MapExpr(SyntaxNode);
+pub struct MissingExpr(SyntaxNode);
+pub struct PostfixExpr(SyntaxNode);
+pub struct UnaryExpr(SyntaxNode);
+
+impl AstExpr {
+    pub fn cast(node: SyntaxNode) -> Option<AstExpr> {
+        Some(match node.kind() {
+            NodeKind::SourceFile => return None,
+            NodeKind::Binary => AstExpr::Binary(BinaryExpr(node)),
+            NodeKind::Conditional => AstExpr::Conditional(ConditionalExpr(node)),
+            NodeKind::Error => AstExpr::Error(ErrorExpr(node)),
+            NodeKind::Grouping => AstExpr::Grouping(GroupingExpr(node)),
+            NodeKind::Index => AstExpr::Index(IndexExpr(node)),
+            NodeKind::IndexAssign => AstExpr::IndexAssign(IndexAssignExpr(node)),
+            NodeKind::List => AstExpr::List(ListExpr(node)),
+            NodeKind::Literal => AstExpr::Literal(LiteralExpr(node)),
+            NodeKind::Map => AstExpr::Map(MapExpr(node)),
+            NodeKind::Missing => AstExpr::Missing(MissingExpr(node)),
+            NodeKind::Postfix => AstExpr::Postfix(PostfixExpr(node)),
+            NodeKind::Unary => AstExpr::Unary(UnaryExpr(node)),
+        })
+    }
+
+    pub fn syntax(&self) -> &SyntaxNode {
+        match self {
+            AstExpr::Binary(BinaryExpr(node))
+            | AstExpr::Conditional(ConditionalExpr(node))
+            | AstExpr::Error(ErrorExpr(node))
+            | AstExpr::Grouping(GroupingExpr(node))
+            | AstExpr::Index(IndexExpr(node))
+            | AstExpr::IndexAssign(IndexAssignExpr(node))
+            | AstExpr::List(ListExpr(node))
+            | AstExpr::Literal(LiteralExpr(node))
+            | AstExpr::Map(MapExpr(node))
+            | AstExpr::Missing(MissingExpr(node))
+            | AstExpr::Postfix(PostfixExpr(node))
+            | AstExpr::Unary(UnaryExpr(node)) => node,
+        }
+    }
+}
+
+impl BinaryExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    pub fn left(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 0)
+    }
+
+    pub fn operator(&self) -> Option<SyntaxToken> {
+        significant_tokens(&self.0).into_iter().next()
+    }
+
+    pub fn right(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 1)
+    }
+}
+
+impl ConditionalExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    pub fn condition(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 0)
+    }
+
+    pub fn then_branch(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 1)
+    }
+
+    pub fn else_branch(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 2)
+    }
+}
+
+impl ErrorExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    pub fn expression(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 0)
+    }
+
+    // The unexpected tokens
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        significant_tokens(&self.0)
+    }
+}
+
+impl GroupingExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    pub fn expression(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 0)
+    }
+}
+
+impl IndexExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    pub fn object(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 0)
+    }
+
+    pub fn index(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 1)
+    }
+}
+
+impl IndexAssignExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    pub fn object(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 0)
+    }
+
+    pub fn index(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 1)
+    }
+
+    pub fn operator(&self) -> Option<SyntaxToken> {
+        let mut tokens = significant_tokens(&self.0).into_iter();
+        tokens.find(|token| !matches!(token.kind(), TokenType::LEFT_BRACKET | TokenType::RIGHT_BRACKET))
+    }
+
+    pub fn value(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 2)
+    }
+}
+
+impl ListExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    pub fn elements(&self) -> Vec<AstExpr> {
+        self.0.children().into_iter().filter_map(AstExpr::cast).collect()
+    }
+}
+
+impl LiteralExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    pub fn token(&self) -> Option<SyntaxToken> {
+        significant_tokens(&self.0).into_iter().next()
+    }
+
+    // Same as the scanner's value for the token.
+    pub fn value(&self) -> Literal {
+        let token = self.token()?;
+        match token.kind() {
+            TokenType::TRUE => Some(LiteralValue::Bool(true)),
+            TokenType::FALSE => Some(LiteralValue::Bool(false)),
+            TokenType::NUMBER => f64::from_str(token.text()).ok().map(LiteralValue::Number),
+            TokenType::STRING => Some(LiteralValue::String(String::from(&token.text()[1..token.text().len() - 1]))),
+            _ => None,
+        }
+    }
+}
+
+impl MapExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    // Keys and values
+    pub fn entries(&self) -> Vec<(AstExpr, AstExpr)> {
+        let mut children = self.0.children().into_iter().filter_map(AstExpr::cast);
+        let mut entries = Vec::new();
+        while let (Some(key), Some(value)) = (children.next(), children.next()) {
+            entries.push((key, value));
+        }
+        entries
+    }
+}
+
+impl MissingExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+}
+
+impl PostfixExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    pub fn operand(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 0)
+    }
+
+    pub fn operator(&self) -> Option<SyntaxToken> {
+        significant_tokens(&self.0).into_iter().next()
+    }
+}
+
+impl UnaryExpr {
+    pub fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    pub fn operator(&self) -> Option<SyntaxToken> {
+        significant_tokens(&self.0).into_iter().next()
+    }
+
+    pub fn operand(&self) -> Option<AstExpr> {
+        nth_expr(&self.0, 0)
+    }
+}
+
+fn nth_expr(node: &SyntaxNode, n: usize) -> Option<AstExpr> {
+    node.children().into_iter().nth(n).and_then(AstExpr::cast)
+}
+
+// The node's own tokens, without trivia
+fn significant_tokens(node: &SyntaxNode) -> Vec<SyntaxToken> {
+    let elements = node.children_with_tokens().into_iter();
+    elements.filter_map(|element| match element {
+        SyntaxElement::Token(token) if !token.is_trivia() => Some(token),
+        _ => None,
+    }).collect()
+}
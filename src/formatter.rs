@@ -0,0 +1,389 @@
+use crate::lexer::{Token, TokenType};
+use crate::parser::{infix_precedence, Expr, ExprKind};
+
+pub const DEFAULT_WIDTH: usize = 80;
+
+const INDENT_WIDTH: usize = 4;
+
+// The formatter works in two steps. First the tree is turned into a Doc, which only says where a
+// line may be broken and which parts should be broken together. Then the Doc is rendered, breaking
+// a Group only if it does not fit in what is left of the line. This is the layout algorithm from
+// Wadler's "A prettier printer".
+enum Doc {
+    Text(String),
+    // A space, or a newline if the enclosing group is broken
+    Line,
+    // Nothing, or a newline if the enclosing group is broken
+    SoftLine,
+    // Always a newline. The enclosing groups can't be flat.
+    HardLine,
+    // Prints nothing, but keeps the enclosing groups from being flat
+    BreakParent,
+    Concat(Vec<Doc>),
+    Indent(Box<Doc>),
+    Group(Box<Doc>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+// Formats an expression parsed from `source`. `comments` are the ones returned by
+// Scanner::scan_tokens_with_comments() for that same source. The output always ends with a
+// newline.
+pub fn format(source: &str, expr: &Expr, comments: &[Token], width: usize) -> String {
+    let mut builder = DocBuilder { source, comments, next_comment: 0 };
+    let mut docs = vec![builder.expr(expr)];
+
+    // Whatever comes after the expression stays on the same line if it was there in the source.
+    let mut previous_end = expr.span.end;
+    for comment in &comments[builder.next_comment..] {
+        if source[previous_end..comment.span.start].contains('\n') {
+            docs.push(Doc::HardLine);
+        } else {
+            docs.push(Doc::Text(String::from(" ")));
+        }
+        docs.push(Doc::Text(comment.lexeme.clone()));
+        previous_end = comment.span.end;
+    }
+
+    let mut output = render(&Doc::Concat(docs), width);
+    output.push('\n');
+    output
+}
+
+struct DocBuilder<'a> {
+    source: &'a str,
+    comments: &'a [Token],
+    // Comments are emitted in order, so everything before this one is already in the Doc.
+    next_comment: usize,
+}
+
+impl DocBuilder<'_> {
+    fn expr(&mut self, expr: &Expr) -> Doc {
+        let mut docs = self.comments_before(expr.span.start);
+        let doc = match &expr.kind {
+            ExprKind::Binary { operator, .. } if operator.token_type == TokenType::COMMA => self.comma(expr),
+            // Ranges are written without spaces, like `1..10`.
+            ExprKind::Binary { left, operator, right }
+                if matches!(operator.token_type, TokenType::DOT_DOT | TokenType::DOT_DOT_EQUAL) =>
+            {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                Doc::Concat(vec![left, Doc::Text(operator.lexeme.clone()), right])
+            }
+            ExprKind::Binary { .. } => self.binary(expr),
+            ExprKind::Conditional { condition, then_branch, else_branch } => {
+                let condition = self.expr(condition);
+                let then_branch = self.expr(then_branch);
+                let else_branch = self.expr(else_branch);
+                Doc::Group(Box::new(Doc::Concat(vec![
+                    condition,
+                    Doc::Indent(Box::new(Doc::Concat(vec![
+                        Doc::Line,
+                        Doc::Text(String::from("? ")),
+                        then_branch,
+                        Doc::Line,
+                        Doc::Text(String::from(": ")),
+                        else_branch,
+                    ]))),
+                ])))
+            }
+            ExprKind::Grouping { expression } => {
+                let inner = vec![self.expr(expression)];
+                self.bracketed("(", inner, ")", expr.span.end - 1)
+            }
+            ExprKind::Index { object, index } => {
+                let object = self.expr(object);
+                let inner = vec![self.expr(index)];
+                Doc::Concat(vec![object, self.bracketed("[", inner, "]", expr.span.end - 1)])
+            }
+            ExprKind::IndexAssign { object, index, closing, operator, value } => {
+                let object = self.expr(object);
+                let inner = vec![self.expr(index)];
+                let index = self.bracketed("[", inner, "]", closing.start);
+                let value = self.expr(value);
+                Doc::Group(Box::new(Doc::Concat(vec![
+                    object,
+                    index,
+                    Doc::Text(format!(" {}", operator.lexeme)),
+                    Doc::Indent(Box::new(Doc::Concat(vec![Doc::Line, value]))),
+                ])))
+            }
+            // Broken lists and maps get one element per line, like comma expressions.
+            ExprKind::List { elements } => {
+                let mut inner = Vec::new();
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        inner.push(Doc::Text(String::from(",")));
+                        inner.push(Doc::Line);
+                    }
+                    inner.push(self.expr(element));
+                }
+                self.bracketed("[", inner, "]", expr.span.end - 1)
+            }
+            // Code that does not parse, which only comes from Parser::parse_tolerant(), is kept as
+            // it is, comments included.
+            ExprKind::Error { .. } | ExprKind::Missing => {
+                while self.comments.get(self.next_comment).is_some_and(|comment| comment.span.end <= expr.span.end) {
+                    self.next_comment += 1;
+                }
+                Doc::Text(String::from(&self.source[expr.span.start..expr.span.end]))
+            }
+            ExprKind::Map { entries } => {
+                let mut inner = Vec::new();
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        inner.push(Doc::Text(String::from(",")));
+                        inner.push(Doc::Line);
+                    }
+                    inner.push(self.expr(key));
+                    inner.push(Doc::Text(String::from(": ")));
+                    inner.push(self.expr(value));
+                }
+                self.bracketed("{", inner, "}", expr.span.end - 1)
+            }
+            // Literals are written the way the user wrote them, so `1.50` stays `1.50`.
+            ExprKind::Literal { .. } => Doc::Text(String::from(&self.source[expr.span.start..expr.span.end])),
+            ExprKind::Postfix { left, operator } =>
+                Doc::Concat(vec![self.expr(left), Doc::Text(operator.lexeme.clone())]),
+            // The space stays when the operator would merge with what follows it, like `- -1` into a
+            // decrement or `~ /* c */` into `~/`. What follows may be a comment.
+            ExprKind::Unary { operator, right } => {
+                let mut text = operator.lexeme.clone();
+                let next = match self.comments.get(self.next_comment) {
+                    Some(comment) if comment.span.start < right.span.start => comment.lexeme.chars().next(),
+                    _ => self.source[right.span.start..].chars().next(),
+                };
+                if next.is_some_and(|next| merges(&text, next)) {
+                    text.push(' ');
+                }
+                Doc::Concat(vec![Doc::Text(text), self.expr(right)])
+            }
+        };
+        if docs.is_empty() {
+            return doc;
+        }
+        docs.push(doc);
+        Doc::Concat(docs)
+    }
+
+    // A chain of operators with the same precedence, like `a + b - c`, is laid out as a whole:
+    // either all on one line or with a line break before each operator.
+    fn binary(&mut self, expr: &Expr) -> Doc {
+        let mut rest: Vec<(&Token, &Expr)> = Vec::new();
+        let mut first: &Expr = expr;
+        let mut precedence = None;
+        while let ExprKind::Binary { left, operator, right } = &first.kind {
+            let operator_precedence = infix_precedence(operator.token_type);
+            if precedence.is_some() && operator_precedence != precedence {
+                break;
+            }
+            precedence = operator_precedence;
+            rest.push((operator, right));
+            first = left;
+        }
+
+        let first = self.expr(first);
+        let mut docs = Vec::new();
+        for (operator, right) in rest.into_iter().rev() {
+            docs.push(Doc::Line);
+            docs.push(Doc::Text(format!("{} ", operator.lexeme)));
+            docs.push(self.expr(right));
+        }
+        Doc::Group(Box::new(Doc::Concat(vec![first, Doc::Indent(Box::new(Doc::Concat(docs)))])))
+    }
+
+    // Broken comma expressions get one operand per line.
+    fn comma(&mut self, expr: &Expr) -> Doc {
+        let mut rest: Vec<&Expr> = Vec::new();
+        let mut first: &Expr = expr;
+        while let ExprKind::Binary { left, operator, right } = &first.kind {
+            if operator.token_type != TokenType::COMMA {
+                break;
+            }
+            rest.push(right);
+            first = left;
+        }
+
+        let mut docs = vec![self.expr(first)];
+        for right in rest.into_iter().rev() {
+            docs.push(Doc::Text(String::from(",")));
+            docs.push(Doc::Line);
+            docs.push(self.expr(right));
+        }
+        Doc::Group(Box::new(Doc::Concat(docs)))
+    }
+
+    // `inner` between brackets, which go on their own lines when the group is broken.
+    // `closing_position` is where the closing bracket is.
+    fn bracketed(&mut self, opening: &str, mut inner: Vec<Doc>, closing: &str, closing_position: usize) -> Doc {
+        inner.insert(0, Doc::SoftLine);
+        inner.extend(self.comments_before_closing(closing_position));
+        Doc::Group(Box::new(Doc::Concat(vec![
+            Doc::Text(String::from(opening)),
+            Doc::Indent(Box::new(Doc::Concat(inner))),
+            Doc::SoftLine,
+            Doc::Text(String::from(closing)),
+        ])))
+    }
+
+    // Comments that go right before something starting at `position`.
+    fn comments_before(&mut self, position: usize) -> Vec<Doc> {
+        let mut docs = Vec::new();
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.start >= position {
+                break;
+            }
+            docs.push(Doc::Text(comment.lexeme.clone()));
+            if comment.lexeme.starts_with("//") {
+                docs.push(Doc::HardLine);
+            } else {
+                docs.push(Doc::Text(String::from(" ")));
+            }
+            self.next_comment += 1;
+        }
+        docs
+    }
+
+    // Comments that go right before a closing parenthesis at `position`. Those follow the last
+    // thing in the group instead of preceding the next one.
+    fn comments_before_closing(&mut self, position: usize) -> Vec<Doc> {
+        let mut docs = Vec::new();
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.start >= position {
+                break;
+            }
+            docs.push(Doc::Text(String::from(" ")));
+            docs.push(Doc::Text(comment.lexeme.clone()));
+            if comment.lexeme.starts_with("//") {
+                docs.push(Doc::BreakParent);
+            }
+            self.next_comment += 1;
+        }
+        docs
+    }
+}
+
+// Whether `text` followed by `next` no longer starts with the token `text` on its own.
+fn merges(text: &str, next: char) -> bool {
+    MERGES.contains(&(text, next))
+}
+
+// The tokens that one more character turns into a longer token, and that character. A '/' also
+// turns into a comment.
+const MERGES: &[(&str, char)] = &[
+    (".", '.'),
+    ("..", '='),
+    ("-", '-'),
+    ("-", '='),
+    ("+", '+'),
+    ("+", '='),
+    ("%", '='),
+    ("*", '*'),
+    ("*", '='),
+    ("~", '/'),
+    ("!", '='),
+    ("=", '='),
+    ("<", '='),
+    ("<", '<'),
+    (">", '='),
+    (">", '>'),
+    ("/", '='),
+    ("/", '/'),
+    ("/", '*'),
+];
+
+fn render(doc: &Doc, width: usize) -> String {
+    let mut output = String::new();
+    let mut column: usize = 0;
+    let mut stack: Vec<(usize, Mode, &Doc)> = vec![(0, Mode::Break, doc)];
+
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(text) => {
+                output.push_str(text);
+                column = match text.rfind('\n') {
+                    Some(newline) => text[newline + 1..].chars().count(),
+                    None => column + text.chars().count(),
+                };
+            }
+            Doc::Line | Doc::SoftLine if mode == Mode::Flat => {
+                if let Doc::Line = doc {
+                    output.push(' ');
+                    column += 1;
+                }
+            }
+            Doc::Line | Doc::SoftLine | Doc::HardLine => {
+                while output.ends_with(' ') {
+                    output.pop();
+                }
+                output.push('\n');
+                output.push_str(&" ".repeat(indent));
+                column = indent;
+            }
+            Doc::BreakParent => (),
+            Doc::Concat(docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((indent, mode, doc));
+                }
+            }
+            Doc::Indent(doc) => stack.push((indent + INDENT_WIDTH, mode, doc)),
+            Doc::Group(doc) => {
+                let remaining = width as isize - column as isize;
+                let group_mode = if mode == Mode::Flat || fits(remaining, (indent, Mode::Flat, doc), &stack) {
+                    Mode::Flat
+                } else {
+                    Mode::Break
+                };
+                stack.push((indent, group_mode, doc));
+            }
+        }
+    }
+
+    output
+}
+
+// Whether `next` fits in `remaining` columns when printed flat, along with whatever follows it on
+// the same line. `rest` is the render stack, so what follows is at its end.
+fn fits(mut remaining: isize, next: (usize, Mode, &Doc), rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut stack = vec![next];
+    let mut rest_index = rest.len();
+
+    loop {
+        if remaining < 0 {
+            return false;
+        }
+        let (indent, mode, doc) = match stack.pop() {
+            Some(entry) => entry,
+            None if rest_index == 0 => return true,
+            None => {
+                rest_index -= 1;
+                rest[rest_index]
+            }
+        };
+        match doc {
+            Doc::Text(text) => match text.find('\n') {
+                Some(newline) => return remaining >= text[..newline].chars().count() as isize,
+                None => remaining -= text.chars().count() as isize,
+            },
+            Doc::Line | Doc::SoftLine if mode == Mode::Break => return true,
+            Doc::Line => remaining -= 1,
+            Doc::SoftLine => (),
+            Doc::HardLine => return mode == Mode::Break,
+            Doc::BreakParent => {
+                if mode == Mode::Flat {
+                    return false;
+                }
+            }
+            Doc::Concat(docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((indent, mode, doc));
+                }
+            }
+            Doc::Indent(doc) | Doc::Group(doc) => stack.push((indent, mode, doc)),
+        }
+    }
+}
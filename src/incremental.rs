@@ -0,0 +1,127 @@
+use std::rc::Rc;
+use crate::cst::{self, GreenNode, GreenToken, NodeKind, SyntaxElement, SyntaxNode};
+use crate::lexer::{Scanner, Span, TokenType};
+use crate::parser::ParseError;
+
+// Incremental reparsing, for editors that parse again on every keystroke. An edit is applied to
+// the previous Parse, and only the part of the tree it touches is scanned and parsed again:
+// 1. If the edit stays within one token and that token scans the same way afterwards, e.g. a
+//    digit typed in a number, only the token is replaced. The tree keeps its shape.
+// 2. Otherwise, if the edit is between the parentheses of a group, that group alone is scanned
+//    and parsed again, starting from the innermost one, and replaces the old group.
+// 3. Otherwise, everything is scanned and parsed again.
+// Either way, the result is the same as a full parse of the new text, and whatever the edit did
+// not touch is shared with the previous green tree.
+//
+// Only parses without errors are updated in place. Errors carry line numbers, which an edit may
+// shift, so a parse with errors always goes through step 3.
+pub struct Parse {
+    root: SyntaxNode,
+    // The scanner's errors, then the parser's
+    errors: Vec<ParseError>,
+}
+
+// Replaces the text in `span` with `text`.
+#[derive(Clone, Debug)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+impl Parse {
+    pub fn new(source: &str) -> Parse {
+        let (tokens, trivia, mut errors) = Scanner::without_runner(String::from(source)).scan_tokens_with_errors();
+        let (root, parse_errors) = cst::parse(tokens, trivia);
+        errors.extend(parse_errors);
+        Parse { root, errors }
+    }
+
+    pub fn root(&self) -> &SyntaxNode {
+        &self.root
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    pub fn text(&self) -> String {
+        self.root.text()
+    }
+
+    pub fn edit(&self, edit: &TextEdit) -> Parse {
+        assert!(edit.span.start <= edit.span.end && edit.span.end <= self.root.span().end, "edit out of range");
+        if self.errors.is_empty() {
+            if let Some(green) = self.relex_token(edit).or_else(|| self.reparse_group(edit)) {
+                return Parse { root: SyntaxNode::new_root(green), errors: Vec::new() };
+            }
+        }
+        Parse::new(&apply(&self.text(), edit, 0))
+    }
+
+    // Step 1. The token is scanned again along with its neighbours, since an edit at its edge
+    // could make it merge with one of them, as in `a` `=` turning into `a` `==`.
+    fn relex_token(&self, edit: &TextEdit) -> Option<Rc<GreenNode>> {
+        let Span { start, end } = edit.span;
+        // For an insertion, the token before the cursor, so that typing at the end of a number
+        // extends it.
+        let token = self.root.token_at(if start == end { start.checked_sub(1)? } else { start })?;
+        let span = token.span();
+        if end > span.end {
+            return None;
+        }
+        let text = apply(token.text(), edit, span.start);
+
+        let previous = span.start.checked_sub(1).and_then(|offset| self.root.token_at(offset));
+        let next = self.root.token_at(span.end);
+        let mut expected: Vec<(TokenType, String)> = Vec::new();
+        expected.extend(previous.map(|previous| (previous.kind(), String::from(previous.text()))));
+        expected.push((token.kind(), text.clone()));
+        expected.extend(next.map(|next| (next.kind(), String::from(next.text()))));
+
+        let source: String = expected.iter().map(|(_, text)| text.as_str()).collect();
+        let (tokens, trivia, errors) = Scanner::without_runner(source).scan_tokens_with_errors();
+        let mut scanned: Vec<_> = tokens.into_iter().chain(trivia).filter(|token| token.token_type != TokenType::EOF).collect();
+        scanned.sort_by_key(|token| token.span.start);
+        let scanned: Vec<(TokenType, String)> = scanned.into_iter().map(|token| (token.token_type, token.lexeme)).collect();
+        if !errors.is_empty() || scanned != expected {
+            return None;
+        }
+        Some(token.replace_with(GreenToken::new(token.kind(), text)))
+    }
+
+    // Step 2. A group that still parses as a single group, with no errors, parses the same way
+    // within the whole expression, since the parser never looks past its closing parenthesis.
+    fn reparse_group(&self, edit: &TextEdit) -> Option<Rc<GreenNode>> {
+        let Span { start, end } = edit.span;
+        // The groups with the edit strictly between their parentheses, the outermost first
+        let mut groups: Vec<SyntaxNode> = Vec::new();
+        let mut node = self.root.clone();
+        while let Some(child) = node.children().into_iter().find(|child| child.span().start < start && end < child.span().end) {
+            if child.kind() == NodeKind::Grouping {
+                groups.push(child.clone());
+            }
+            node = child;
+        }
+
+        groups.iter().rev().find_map(|group| {
+            let text = apply(&group.text(), edit, group.span().start);
+            let (tokens, trivia, scan_errors) = Scanner::without_runner(text).scan_tokens_with_errors();
+            let (root, errors) = cst::parse(tokens, trivia);
+            if !scan_errors.is_empty() || !errors.is_empty() {
+                return None;
+            }
+            // Nothing but the group and the EOF token
+            match root.children_with_tokens().as_slice() {
+                [SyntaxElement::Node(new_group), SyntaxElement::Token(_)] if new_group.kind() == NodeKind::Grouping =>
+                    Some(group.replace_with(new_group.green().clone())),
+                _ => None,
+            }
+        })
+    }
+}
+
+// Applies `edit` to `text`, which starts at `offset` in the source.
+fn apply(text: &str, edit: &TextEdit, offset: usize) -> String {
+    let (start, end) = (edit.span.start - offset, edit.span.end - offset);
+    format!("{}{}{}", &text[..start], edit.text, &text[end..])
+}
@@ -0,0 +1,210 @@
+use std::fmt;
+use crate::lexer::{Literal, LiteralValue, Span, Token};
+use crate::parser::Expr;
+use crate::visitor::{walk_expr, Visitor};
+
+// JSON views of the token stream and of the AST, for tools that are not written in Rust.
+//
+// A token looks like
+//   {"type":"NUMBER","lexeme":"1.5","literal":1.5,"line":1,"span":{"start":0,"end":3}}
+// and an expression node like
+//   {"id":2,"span":{"start":0,"end":5},"kind":"Binary","left":{...},"operator":{...},"right":{...}}
+// where the fields after "kind" are the ones of the matching parser::ExprKind variant and tokens
+// are written as above. Literal values are JSON strings, numbers, booleans or null (for nil), and
+// so is the expression of an Error node that has none.
+
+pub fn tokens_to_json(tokens: &[Token]) -> String {
+    Json::Array(tokens.iter().map(token).collect()).to_string()
+}
+
+pub fn expr_to_json(expr: &Expr) -> String {
+    let mut writer = JsonWriter { stack: Vec::new() };
+    writer.visit_expr(expr);
+    writer.stack.pop().unwrap().to_string()
+}
+
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // Keys are kept in insertion order, which makes the output stable.
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{value}"),
+            Json::Number(value) => write!(f, "{value}"),
+            Json::String(value) => write_string(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+fn literal(value: &Literal) -> Json {
+    match value {
+        None => Json::Null,
+        Some(LiteralValue::Bool(value)) => Json::Bool(*value),
+        Some(LiteralValue::Number(value)) => Json::Number(*value),
+        Some(LiteralValue::String(value)) => Json::String(value.clone()),
+    }
+}
+
+fn span(span: Span) -> Json {
+    Json::Object(vec![
+        ("start", Json::Number(span.start as f64)),
+        ("end", Json::Number(span.end as f64)),
+    ])
+}
+
+fn token(token: &Token) -> Json {
+    Json::Object(vec![
+        ("type", Json::String(token.token_type.to_string())),
+        ("lexeme", Json::String(token.lexeme.clone())),
+        ("literal", literal(&token.literal)),
+        ("line", Json::Number(token.line as f64)),
+        ("span", span(token.span)),
+    ])
+}
+
+// Each visit_* method leaves the fields of its node on the stack. visit_expr() then wraps them
+// with the id and span of the node.
+struct JsonWriter {
+    stack: Vec<Json>,
+}
+
+impl JsonWriter {
+    fn child(&mut self, expr: &Expr) -> Json {
+        self.visit_expr(expr);
+        self.stack.pop().unwrap()
+    }
+
+    fn push_kind(&mut self, kind: &'static str, mut fields: Vec<(&'static str, Json)>) {
+        fields.insert(0, ("kind", Json::String(String::from(kind))));
+        self.stack.push(Json::Object(fields));
+    }
+}
+
+impl Visitor for JsonWriter {
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+        let Some(Json::Object(fields)) = self.stack.pop() else { unreachable!() };
+        let mut node = vec![("id", Json::Number(expr.id.0 as f64)), ("span", span(expr.span))];
+        node.extend(fields);
+        self.stack.push(Json::Object(node));
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        let fields = vec![("left", self.child(left)), ("operator", token(operator)), ("right", self.child(right))];
+        self.push_kind("Binary", fields);
+    }
+
+    fn visit_conditional(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) {
+        let fields = vec![
+            ("condition", self.child(condition)),
+            ("then_branch", self.child(then_branch)),
+            ("else_branch", self.child(else_branch)),
+        ];
+        self.push_kind("Conditional", fields);
+    }
+
+    fn visit_error(&mut self, expression: Option<&Expr>, tokens: &[Token]) {
+        let expression = match expression {
+            Some(expression) => self.child(expression),
+            None => Json::Null,
+        };
+        let fields = vec![("expression", expression), ("tokens", Json::Array(tokens.iter().map(token).collect()))];
+        self.push_kind("Error", fields);
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) {
+        let fields = vec![("expression", self.child(expression))];
+        self.push_kind("Grouping", fields);
+    }
+
+    fn visit_index(&mut self, object: &Expr, index: &Expr) {
+        let fields = vec![("object", self.child(object)), ("index", self.child(index))];
+        self.push_kind("Index", fields);
+    }
+
+    fn visit_index_assign(&mut self, object: &Expr, index: &Expr, operator: &Token, value: &Expr) {
+        let fields = vec![
+            ("object", self.child(object)),
+            ("index", self.child(index)),
+            ("operator", token(operator)),
+            ("value", self.child(value)),
+        ];
+        self.push_kind("IndexAssign", fields);
+    }
+
+    fn visit_list(&mut self, elements: &[Expr]) {
+        let elements = elements.iter().map(|element| self.child(element)).collect();
+        self.push_kind("List", vec![("elements", Json::Array(elements))]);
+    }
+
+    fn visit_literal(&mut self, value: &Literal) {
+        self.push_kind("Literal", vec![("value", literal(value))]);
+    }
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)]) {
+        let entries = entries
+            .iter()
+            .map(|(key, value)| Json::Object(vec![("key", self.child(key)), ("value", self.child(value))]))
+            .collect();
+        self.push_kind("Map", vec![("entries", Json::Array(entries))]);
+    }
+
+    fn visit_missing(&mut self) {
+        self.push_kind("Missing", Vec::new());
+    }
+
+    fn visit_postfix(&mut self, left: &Expr, operator: &Token) {
+        let fields = vec![("left", self.child(left)), ("operator", token(operator))];
+        self.push_kind("Postfix", fields);
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) {
+        let fields = vec![("operator", token(operator)), ("right", self.child(right))];
+        self.push_kind("Unary", fields);
+    }
+}
@@ -1,13 +1,52 @@
 use std::{fmt, str::FromStr};
 use crate::Lox;
+use crate::parser::ParseError;
 
-pub type Literal = Option<Box<dyn fmt::Display>>;
+#[derive(Clone, Debug, PartialEq)]
+pub enum LiteralValue {
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl fmt::Display for LiteralValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LiteralValue::Bool(value) => write!(f, "{value}"),
+            LiteralValue::Number(value) => write!(f, "{value}"),
+            LiteralValue::String(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+// nil is represented by None
+pub type Literal = Option<LiteralValue>;
+
+// Byte offsets into the source, end exclusive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
 
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    // The smallest span covering both self and other.
+    pub fn to(self, other: Span) -> Span {
+        Span { start: self.start.min(other.start), end: self.end.max(other.end) }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Literal,
     pub line: usize,
+    pub span: Span,
 }
 
 impl fmt::Display for Token {
@@ -17,9 +56,7 @@ impl fmt::Display for Token {
             "{} {} {}",
             self.token_type,
             self.lexeme,
-            self.literal.as_ref()
-            .or(Some(Box::new("null") as Box<dyn fmt::Display>).as_ref())
-            .unwrap(),
+            self.literal.as_ref().map_or(String::from("null"), |value| value.to_string()),
         )
     }
 }
@@ -27,25 +64,72 @@ impl fmt::Display for Token {
 pub struct Scanner<'a> {
     source: String,
     tokens: Vec<Token>,
+    // Comments are not part of the grammar, so they are kept out of the token stream. Tools that
+    // need them back, like the formatter, can get them through scan_tokens_with_comments().
+    comments: Vec<Token>,
+    // Everything else in the source that is not a token: whitespace, comments and characters the
+    // scanner could not make sense of, for tools that must not lose a byte, like cst.rs.
+    trivia: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
-    runner: &'a mut Lox,
+    // Errors are reported to the runner, if any, and kept either way.
+    runner: Option<&'a mut Lox>,
+    errors: Vec<ParseError>,
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(source: String, runner: &'a mut Lox) -> Self {
+        Scanner::with_runner(source, Some(runner))
+    }
+
+    // For tools that deal with the errors themselves, like incremental.rs: nothing gets printed,
+    // and scan_tokens_with_errors() returns the errors instead.
+    pub fn without_runner(source: String) -> Scanner<'static> {
+        Scanner::with_runner(source, None)
+    }
+
+    fn with_runner(source: String, runner: Option<&'a mut Lox>) -> Self {
         Scanner {
             source,
             tokens: Vec::new(),
+            comments: Vec::new(),
+            trivia: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
             runner,
+            errors: Vec::new(),
         }
     }
 
-    pub fn scan_tokens(&mut self) -> &Vec<Token> {
+    pub fn scan_tokens(self) -> Vec<Token> {
+        self.scan_tokens_with_comments().0
+    }
+
+    pub fn scan_tokens_with_comments(self) -> (Vec<Token>, Vec<Token>) {
+        let (tokens, comments, _) = self.scan_all();
+        (tokens, comments)
+    }
+
+    // The tokens and the trivia between them. Together, they cover every byte of the source.
+    pub fn scan_tokens_with_trivia(self) -> (Vec<Token>, Vec<Token>) {
+        let (tokens, trivia, _) = self.scan_tokens_with_errors();
+        (tokens, trivia)
+    }
+
+    // Same as scan_tokens_with_trivia(), along with the errors found.
+    pub fn scan_tokens_with_errors(mut self) -> (Vec<Token>, Vec<Token>, Vec<ParseError>) {
+        self.scan();
+        (self.tokens, self.trivia, self.errors)
+    }
+
+    fn scan_all(mut self) -> (Vec<Token>, Vec<Token>, Vec<Token>) {
+        self.scan();
+        (self.tokens, self.comments, self.trivia)
+    }
+
+    fn scan(&mut self) {
         while !self.is_at_end() {
             // We are at the beginning of the next lexeme
             self.start = self.current;
@@ -55,10 +139,10 @@ impl<'a> Scanner<'a> {
             token_type: TokenType::EOF,
             lexeme: String::from(""),
             literal: None,
-            line: 1,
+            line: self.line,
+            span: Span::new(self.current, self.current),
         };
         self.tokens.push(t);
-        &self.tokens
     }
 
     fn scan_token(&mut self) {
@@ -67,12 +151,68 @@ impl<'a> Scanner<'a> {
             Some(')') => self.add_token(TokenType::RIGHT_PAREN, None),
             Some('{') => self.add_token(TokenType::LEFT_BRACE, None),
             Some('}') => self.add_token(TokenType::RIGHT_BRACE, None),
+            Some('[') => self.add_token(TokenType::LEFT_BRACKET, None),
+            Some(']') => self.add_token(TokenType::RIGHT_BRACKET, None),
             Some(',') => self.add_token(TokenType::COMMA, None),
-            Some('.') => self.add_token(TokenType::DOT, None),
-            Some('-') => self.add_token(TokenType::MINUS, None),
-            Some('+') => self.add_token(TokenType::PLUS, None),
+            Some('?') => self.add_token(TokenType::QUESTION, None),
+            Some(':') => self.add_token(TokenType::COLON, None),
+            Some('.') => {
+                if self.match_next('.') {
+                    if self.match_next('=') {
+                        self.add_token(TokenType::DOT_DOT_EQUAL, None);
+                    } else {
+                        self.add_token(TokenType::DOT_DOT, None);
+                    }
+                } else {
+                    self.add_token(TokenType::DOT, None);
+                }
+            }
+            Some('-') => {
+                if self.match_next('-') {
+                    self.add_token(TokenType::MINUS_MINUS, None);
+                } else if self.match_next('=') {
+                    self.add_token(TokenType::MINUS_EQUAL, None);
+                } else {
+                    self.add_token(TokenType::MINUS, None);
+                }
+            }
+            Some('+') => {
+                if self.match_next('+') {
+                    self.add_token(TokenType::PLUS_PLUS, None);
+                } else if self.match_next('=') {
+                    self.add_token(TokenType::PLUS_EQUAL, None);
+                } else {
+                    self.add_token(TokenType::PLUS, None);
+                }
+            }
             Some(';') => self.add_token(TokenType::SEMICOLON, None),
-            Some('*') => self.add_token(TokenType::STAR, None),
+            Some('%') => {
+                if self.match_next('=') {
+                    self.add_token(TokenType::PERCENT_EQUAL, None);
+                } else {
+                    self.add_token(TokenType::PERCENT, None);
+                }
+            }
+            Some('*') => {
+                if self.match_next('*') {
+                    self.add_token(TokenType::STAR_STAR, None);
+                } else if self.match_next('=') {
+                    self.add_token(TokenType::STAR_EQUAL, None);
+                } else {
+                    self.add_token(TokenType::STAR, None);
+                }
+            }
+            // Integer division. `//` would have been the obvious spelling, but it starts a comment.
+            Some('~') => {
+                if self.match_next('/') {
+                    self.add_token(TokenType::TILDE_SLASH, None);
+                } else {
+                    self.add_token(TokenType::TILDE, None);
+                }
+            }
+            Some('&') => self.add_token(TokenType::AMPERSAND, None),
+            Some('|') => self.add_token(TokenType::PIPE, None),
+            Some('^') => self.add_token(TokenType::CARET, None),
             Some('!') => {
                 if self.match_next('=') {
                     self.add_token(TokenType::BANG_EQUAL, None);
@@ -90,6 +230,8 @@ impl<'a> Scanner<'a> {
             Some('<') => {
                 if self.match_next('=') {
                     self.add_token(TokenType::LESS_EQUAL, None);
+                } else if self.match_next('<') {
+                    self.add_token(TokenType::LESS_LESS, None);
                 } else {
                     self.add_token(TokenType::LESS, None);
                 }
@@ -97,6 +239,8 @@ impl<'a> Scanner<'a> {
             Some('>') => {
                 if self.match_next('=') {
                     self.add_token(TokenType::GREATER_EQUAL, None);
+                } else if self.match_next('>') {
+                    self.add_token(TokenType::GREATER_GREATER, None);
                 } else {
                     self.add_token(TokenType::GREATER, None);
                 }
@@ -107,19 +251,22 @@ impl<'a> Scanner<'a> {
                     while self.peek() != Some('\n') && !self.is_at_end() {
                         self.advance();
                     }
+                    self.add_comment();
                 } else if self.match_next('*') {
                     self.block_comment();
+                } else if self.match_next('=') {
+                    self.add_token(TokenType::SLASH_EQUAL, None);
                 } else {
                     self.add_token(TokenType::SLASH, None);
                 }
             }
-            Some(' ') | Some ('\r') | Some('\t') => (),
-            Some('\n') => self.line += 1,
+            Some(' ') | Some ('\r') | Some('\t') | Some('\n') => self.whitespace(),
             Some('"') => self.string(),
             Some('0'..='9') => self.number(),
             Some('A'..='Z') | Some('a'..='z') | Some('_') => self.identifier(),
             Some(entry) => {
-                self.runner.error(self.line, String::from(format!("Unexpected character '{entry}'")));
+                self.error(format!("Unexpected character '{entry}'"));
+                self.add_trivia(TokenType::UNKNOWN);
             }
             None => (),
         };
@@ -128,11 +275,13 @@ impl<'a> Scanner<'a> {
     fn block_comment(&mut self) {
         loop {
             if self.is_at_end() {
-                self.runner.error(self.line, String::from(format!("Unfinished block comment")));
+                self.error(String::from("Unfinished block comment"));
+                self.add_trivia(TokenType::UNKNOWN);
                 break;
             }
             if self.peek() == Some('*') && self.peek_next() == Some('/') {
                 self.current += 2;
+                self.add_comment();
                 break;
             }
             if self.peek() == Some ('\n') {
@@ -142,14 +291,26 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    // Called with the first whitespace character already consumed.
+    fn whitespace(&mut self) {
+        if self.source[self.start..self.current].ends_with('\n') {
+            self.line += 1;
+        }
+        while let Some(' ') | Some('\r') | Some('\t') | Some('\n') = self.peek() {
+            if self.advance() == Some('\n') {
+                self.line += 1;
+            }
+        }
+        self.add_trivia(TokenType::WHITESPACE);
+    }
+
     fn identifier(&mut self) {
         while let Some('0'..='9') | Some('A'..='Z') | Some('a'..='z') | Some('_') = self.peek() {
             self.advance();
         }
-        // TODO: we need to account for utf8 data here. the slice below is quite error prone
         let text = &self.source[self.start..self.current];
-        let true_literal: Literal = Some(Box::new(false));
-        let false_literal: Literal = Some(Box::new(false));
+        let true_literal: Literal = Some(LiteralValue::Bool(true));
+        let false_literal: Literal = Some(LiteralValue::Bool(false));
         let (identifier_type, literal) = match text {
             "and" => (TokenType::AND, None),
             "class" => (TokenType::CLASS, None),
@@ -176,6 +337,8 @@ impl<'a> Scanner<'a> {
         while let Some('0'..='9') = self.peek() {
             self.advance();
         }
+        // Only a digit after the '.' makes it a decimal point, so `1..2` is a range and `1.x` a
+        // property access.
         if self.peek() == Some('.') {
             if let Some('0'..='9') = self.peek_next() {
                 // Condume the first '.'
@@ -186,9 +349,8 @@ impl<'a> Scanner<'a> {
                 }
             }
         }
-        // TODO: we need to account for utf8 data here. the slice below is quite error prone
         let value: f64 = f64::from_str(&self.source[self.start..self.current]).unwrap();
-        self.add_token(TokenType::NUMBER, Some(Box::new(value)));
+        self.add_token(TokenType::NUMBER, Some(LiteralValue::Number(value)));
     }
 
     fn string(&mut self) {
@@ -200,44 +362,47 @@ impl<'a> Scanner<'a> {
         }
 
         if self.is_at_end() {
-            self.runner.error(self.line, String::from(format!("Unterminated string.")));
-            return ();
+            self.error(String::from("Unterminated string."));
+            self.add_trivia(TokenType::UNKNOWN);
+            return;
         }
 
         // now get the closing '"'
         self.advance();
         // trim the surrounding quotes
-        // TODO: we need to account for utf8 data here. the slice below is quite error prone
         let value = String::from(&self.source[(self.start + 1)..(self.current - 1)]);
-        self.add_token(TokenType::STRING, Some(Box::new(value)));
+        self.add_token(TokenType::STRING, Some(LiteralValue::String(value)));
     }
 
+    // `current` is a byte offset, so the source is read from there rather than with chars().nth(),
+    // which would count characters instead.
     fn peek(&self) -> Option<char> {
         if self.is_at_end() {
             return Some('\0');
         }
-        self.source.chars().nth(self.current)
+        self.source[self.current..].chars().next()
     }
 
     fn peek_next(&self) -> Option<char> {
-        if self.current + 1 >= self.source.len() {
-            return Some('\0');
-        }
-        self.source.chars().nth(self.current + 1)
+        let mut chars = self.source[self.current..].chars();
+        chars.next();
+        chars.next().or(Some('\0'))
     }
 
     fn match_next(&mut self, expected: char) -> bool {
         if self.is_at_end() { return false; }
-        if self.source.chars().nth(self.current) == Some(expected) {
-            self.current += 1;
+        if self.source[self.current..].starts_with(expected) {
+            self.current += expected.len_utf8();
             return true;
         }
         false
     }
 
     fn advance(&mut self) -> Option<char> {
-        let next = self.source.chars().nth(self.current);
-        self.current += 1;
+        let next = self.source[self.current..].chars().next();
+        if let Some(c) = next {
+            self.current += c.len_utf8();
+        }
         next
     }
 
@@ -245,30 +410,123 @@ impl<'a> Scanner<'a> {
         self.current >= self.source.len()
     }
 
+    fn error(&mut self, message: String) {
+        if let Some(runner) = self.runner.as_deref_mut() {
+            runner.error(self.line, message.clone());
+        }
+        self.errors.push(ParseError { line: self.line, location: String::new(), message });
+    }
+
+    fn add_comment(&mut self) {
+        let text = String::from(&self.source[self.start..self.current]);
+        let span = Span::new(self.start, self.current);
+        self.comments.push(Token {token_type: TokenType::COMMENT, lexeme: text, literal: None, line: self.line, span});
+        self.add_trivia(TokenType::COMMENT);
+    }
+
+    fn add_trivia(&mut self, token_type: TokenType) {
+        let text = String::from(&self.source[self.start..self.current]);
+        let span = Span::new(self.start, self.current);
+        self.trivia.push(Token {token_type, lexeme: text, literal: None, line: self.line, span});
+    }
+
     fn add_token(&mut self, token_type: TokenType, literal: Literal) {
-        // TODO: we need to account for utf8 data here. the slice below is quite error prone
         let text = String::from(&self.source[self.start..self.current]);
-        let line = 0;
-        self.tokens.push(Token {token_type, lexeme: text, literal, line});
+        let span = Span::new(self.start, self.current);
+        self.tokens.push(Token {token_type, lexeme: text, literal, line: self.line, span});
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
 pub enum TokenType {
     // single character tokens
-    LEFT_PAREN, RIGHT_PAREN, LEFT_BRACE, RIGHT_BRACE,
-    COMMA, DOT, MINUS, PLUS, SEMICOLON, SLASH, STAR,
+    LEFT_PAREN, RIGHT_PAREN, LEFT_BRACE, RIGHT_BRACE, LEFT_BRACKET, RIGHT_BRACKET,
+    COMMA, DOT, MINUS, PLUS, SEMICOLON, SLASH, STAR, QUESTION, COLON, PERCENT,
+    AMPERSAND, PIPE, CARET,
     // one or two character tokens
     BANG, BANG_EQUAL, EQUAL, EQUAL_EQUAL, GREATER, GREATER_EQUAL, LESS, LESS_EQUAL,
+    DOT_DOT, DOT_DOT_EQUAL, STAR_STAR, TILDE, TILDE_SLASH, LESS_LESS, GREATER_GREATER,
+    PLUS_PLUS, MINUS_MINUS, PLUS_EQUAL, MINUS_EQUAL, STAR_EQUAL, SLASH_EQUAL, PERCENT_EQUAL,
     // literals
     IDENTIFIER, STRING, NUMBER,
     // keywords
     AND, CLASS, ELSE, FALSE, FUN, FOR, IF, NIL, OR, PRINT, RETURN, SUPER, THIS, TRUE, VAR, WHILE,
+    // never part of the token stream handed to the parser
+    COMMENT, WHITESPACE, UNKNOWN,
 
     EOF,
 }
 
+impl TokenType {
+    // The lexeme of the token types that are always spelled the same way, like operators and
+    // keywords.
+    pub fn lexeme(self) -> Option<&'static str> {
+        let lexeme = match self {
+            TokenType::LEFT_PAREN => "(",
+            TokenType::RIGHT_PAREN => ")",
+            TokenType::LEFT_BRACE => "{",
+            TokenType::RIGHT_BRACE => "}",
+            TokenType::LEFT_BRACKET => "[",
+            TokenType::RIGHT_BRACKET => "]",
+            TokenType::COMMA => ",",
+            TokenType::DOT => ".",
+            TokenType::MINUS => "-",
+            TokenType::PLUS => "+",
+            TokenType::SEMICOLON => ";",
+            TokenType::SLASH => "/",
+            TokenType::STAR => "*",
+            TokenType::QUESTION => "?",
+            TokenType::COLON => ":",
+            TokenType::PERCENT => "%",
+            TokenType::AMPERSAND => "&",
+            TokenType::PIPE => "|",
+            TokenType::CARET => "^",
+            TokenType::BANG => "!",
+            TokenType::BANG_EQUAL => "!=",
+            TokenType::EQUAL => "=",
+            TokenType::EQUAL_EQUAL => "==",
+            TokenType::GREATER => ">",
+            TokenType::GREATER_EQUAL => ">=",
+            TokenType::LESS => "<",
+            TokenType::LESS_EQUAL => "<=",
+            TokenType::DOT_DOT => "..",
+            TokenType::DOT_DOT_EQUAL => "..=",
+            TokenType::STAR_STAR => "**",
+            TokenType::TILDE => "~",
+            TokenType::TILDE_SLASH => "~/",
+            TokenType::LESS_LESS => "<<",
+            TokenType::GREATER_GREATER => ">>",
+            TokenType::PLUS_PLUS => "++",
+            TokenType::MINUS_MINUS => "--",
+            TokenType::PLUS_EQUAL => "+=",
+            TokenType::MINUS_EQUAL => "-=",
+            TokenType::STAR_EQUAL => "*=",
+            TokenType::SLASH_EQUAL => "/=",
+            TokenType::PERCENT_EQUAL => "%=",
+            TokenType::AND => "and",
+            TokenType::CLASS => "class",
+            TokenType::ELSE => "else",
+            TokenType::FALSE => "false",
+            TokenType::FUN => "fun",
+            TokenType::FOR => "for",
+            TokenType::IF => "if",
+            TokenType::NIL => "nil",
+            TokenType::OR => "or",
+            TokenType::PRINT => "print",
+            TokenType::RETURN => "return",
+            TokenType::SUPER => "super",
+            TokenType::THIS => "this",
+            TokenType::TRUE => "true",
+            TokenType::VAR => "var",
+            TokenType::WHILE => "while",
+            TokenType::IDENTIFIER | TokenType::STRING | TokenType::NUMBER => return None,
+            TokenType::COMMENT | TokenType::WHITESPACE | TokenType::UNKNOWN | TokenType::EOF => return None,
+        };
+        Some(lexeme)
+    }
+}
+
 impl fmt::Display for TokenType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Debug::fmt(self, f)
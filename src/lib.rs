@@ -1,14 +1,41 @@
 use std::{fs, io, process};
 use std::io::Write;
-use crate::lexer::Scanner;
+use crate::lexer::{Scanner, Token};
+use crate::parser::{DotPrinter, Expr, ParseError, Parser};
 
+pub mod arena;
+pub mod checker;
+pub mod cst;
+pub mod formatter;
+pub mod incremental;
+pub mod json;
 pub mod lexer;
 pub mod parser;
+pub mod visitor;
 
 pub struct Lox {
     pub had_error: bool,
 }
 
+// Machine-readable views of the front end, printed instead of running a script.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dump {
+    TokensJson,
+    AstJson,
+    AstDot,
+}
+
+// What Lox::format_file() found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatStatus {
+    // Already formatted
+    Unchanged,
+    // Not formatted, so it was rewritten, unless only checking
+    Changed,
+    // Could not be parsed. The errors were reported.
+    Invalid,
+}
+
 impl Lox {
     pub fn run_prompt(&mut self) {
         loop {
@@ -46,8 +73,107 @@ impl Lox {
         };
     }
 
+    // Formats the file at `path` in place, or only checks it when `check` is set.
+    pub fn format_file(&mut self, path: String, check: bool, width: usize) -> FormatStatus {
+        let program = match fs::read_to_string(&path) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(74);
+            }
+        };
+        let formatted = match self.format(program.clone(), width) {
+            Some(formatted) => formatted,
+            None => return FormatStatus::Invalid,
+        };
+        if formatted == program {
+            return FormatStatus::Unchanged;
+        }
+        if check {
+            println!("{path} is not formatted");
+        } else if let Err(e) = fs::write(&path, formatted) {
+            eprintln!("{}", e);
+            process::exit(74);
+        }
+        FormatStatus::Changed
+    }
+
+    // Returns None, after reporting the errors, if the source can't be parsed.
+    pub fn format(&mut self, source: String, width: usize) -> Option<String> {
+        let scanner = Scanner::new(source.clone(), self);
+        let (tokens, comments) = scanner.scan_tokens_with_comments();
+        if self.had_error {
+            return None;
+        }
+
+        let expr = self.parse(tokens)?;
+        Some(formatter::format(&source, &expr, &comments, width))
+    }
+
+    // Type checks the file at `path`. Returns whether it is free of errors.
+    pub fn check_file(&mut self, path: String) -> bool {
+        match fs::read_to_string(path) {
+            Ok(program) => self.check(program),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(74);
+            }
+        }
+    }
+
+    // Reports the scan, parse and type errors in `source`. Returns whether there was none.
+    pub fn check(&mut self, source: String) -> bool {
+        let tokens = Scanner::new(source.clone(), self).scan_tokens();
+        if self.had_error {
+            return false;
+        }
+        let Some(expr) = self.parse(tokens) else {
+            return false;
+        };
+        let (_, errors) = checker::check(&source, &expr);
+        for error in errors.iter() {
+            self.report(error.line, error.location.clone(), error.message.clone());
+        }
+        errors.is_empty()
+    }
+
+    pub fn dump_file(&mut self, path: String, dump: Dump) {
+        match fs::read_to_string(path) {
+            Ok(program) => match self.dump(program, dump) {
+                Some(output) => println!("{output}"),
+                None => process::exit(65),
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+            }
+        };
+    }
+
+    // Returns None, after reporting the errors, if the source can't be scanned (or parsed, when
+    // the dump needs an AST).
+    pub fn dump(&mut self, source: String, dump: Dump) -> Option<String> {
+        let tokens = Scanner::new(source, self).scan_tokens();
+        if self.had_error {
+            return None;
+        }
+        match dump {
+            Dump::TokensJson => Some(json::tokens_to_json(&tokens)),
+            Dump::AstJson => self.parse(tokens).map(|expr| json::expr_to_json(&expr)),
+            Dump::AstDot => self.parse(tokens).map(|expr| DotPrinter.print(&expr)),
+        }
+    }
+
+    fn parse(&mut self, tokens: Vec<Token>) -> Option<Expr> {
+        let parser = Parser::new(tokens);
+        let expr = parser.parse();
+        for error in parser.errors().iter() {
+            self.parse_error(error);
+        }
+        expr
+    }
+
     fn run(&mut self, source: String) {
-        let mut scanner = Scanner::new(source, self);
+        let scanner = Scanner::new(source, self);
         let tokens = scanner.scan_tokens();
 
         for token in tokens {
@@ -59,6 +185,10 @@ impl Lox {
         self.report(line, String::from(""), message);
     }
 
+    fn parse_error(&mut self, error: &ParseError) {
+        self.report(error.line, error.location.clone(), error.message.clone());
+    }
+
     fn report(&mut self, line: usize, location: String, message: String) {
         eprintln!("[line {line}] Error{location}: {message}");
         self.had_error = true;
@@ -1,15 +1,91 @@
 use std::{env, process};
-use yarli::Lox;
+use yarli::{Dump, FormatStatus, Lox};
+use yarli::formatter::DEFAULT_WIDTH;
 
 fn main() {
     let mut lox = Lox { had_error: false };
-    match env::args().len() {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("fmt") {
+        fmt(&mut lox, &args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("check") {
+        check(&mut lox, &args[2..]);
+        return;
+    }
+    match args.len() {
         1 => lox.run_prompt(),
-        2 => lox.run_file(env::args().nth(1).unwrap()),
-        _ => {
-            eprintln!("Usage: {} [script]", env::args().nth(0).unwrap());
-            process::exit(64);
-        },
+        2 => lox.run_file(args[1].clone()),
+        3 => {
+            let dump = match args[1].as_str() {
+                "--tokens-json" => Dump::TokensJson,
+                "--ast-json" => Dump::AstJson,
+                "--ast-dot" => Dump::AstDot,
+                _ => usage(),
+            };
+            lox.dump_file(args[2].clone(), dump);
+        }
+        _ => usage(),
     };
 }
 
+fn fmt(lox: &mut Lox, args: &[String]) {
+    let mut check = false;
+    let mut width = DEFAULT_WIDTH;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--width" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(value) => width = value,
+                None => usage(),
+            },
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if paths.is_empty() {
+        usage();
+    }
+
+    // A file that can't be parsed doesn't stop the others from being formatted.
+    let mut all_formatted = true;
+    let mut all_valid = true;
+    for path in paths {
+        match lox.format_file(path, check, width) {
+            FormatStatus::Unchanged => (),
+            FormatStatus::Changed => all_formatted = false,
+            FormatStatus::Invalid => all_valid = false,
+        }
+        lox.had_error = false;
+    }
+    if !all_valid {
+        process::exit(65);
+    }
+    if check && !all_formatted {
+        process::exit(1);
+    }
+}
+
+fn check(lox: &mut Lox, paths: &[String]) {
+    if paths.is_empty() {
+        usage();
+    }
+    let mut all_checked = true;
+    for path in paths {
+        all_checked &= lox.check_file(path.clone());
+        lox.had_error = false;
+    }
+    if !all_checked {
+        process::exit(65);
+    }
+}
+
+fn usage() -> ! {
+    let program = env::args().next().unwrap();
+    eprintln!("Usage: {program} [script]");
+    eprintln!("       {program} --tokens-json|--ast-json|--ast-dot script");
+    eprintln!("       {program} fmt [--check] [--width N] FILE...");
+    eprintln!("       {program} check FILE...");
+    process::exit(64);
+}
@@ -1,121 +1,689 @@
-use std::cell::Cell;
-use crate::lexer::{Literal, Token, TokenType};
+use std::cell::{Cell, Ref, RefCell};
+use std::fmt;
+use crate::lexer::{Literal, LiteralValue, Span, Token, TokenType};
+use crate::visitor::{
+    walk_binary, walk_conditional, walk_error, walk_expr, walk_grouping, walk_index, walk_index_assign, walk_list, walk_map,
+    walk_postfix, walk_unary, Visitor,
+};
 
-pub enum Expr<'a> {
-    Binary { left: Box<Expr<'a>>, operator: &'a Token, right: Box<Expr<'a>> },
-    Grouping { expression: Box<Expr<'a>> },
-    Literal { value: &'a Literal },
-    Unary { operator: &'a Token, right: Box<Expr<'a>> }
+// Identifies a node within the tree it was parsed into, so passes can keep their results in side
+// tables keyed by it. The parser numbers nodes in the order it builds them, starting at 0, so
+// parsing the same source always yields the same ids.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub u32);
+
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: ExprKind,
+}
+
+#[derive(Clone, Debug)]
+pub enum ExprKind {
+    Binary { left: Box<Expr>, operator: Token, right: Box<Expr> },
+    Conditional { condition: Box<Expr>, then_branch: Box<Expr>, else_branch: Box<Expr> },
+    // Only found in trees from Parser::parse_tolerant(), like Missing. Unexpected tokens, along
+    // with the expression they came after, if any.
+    Error { expression: Option<Box<Expr>>, tokens: Vec<Token> },
+    Grouping { expression: Box<Expr> },
+    // `object[index]`
+    Index { object: Box<Expr>, index: Box<Expr> },
+    // `object[index] = value`. `closing` is the span of the ']'.
+    IndexAssign { object: Box<Expr>, index: Box<Expr>, closing: Span, operator: Token, value: Box<Expr> },
+    List { elements: Vec<Expr> },
+    Literal { value: Literal },
+    // Keys and values, in source order
+    Map { entries: Vec<(Expr, Expr)> },
+    // Stands in for an operand that is not there, e.g. the right one in `1 +`. Its span is empty.
+    Missing,
+    Postfix { left: Box<Expr>, operator: Token },
+    Unary { operator: Token, right: Box<Expr> }
+}
+
+// Binding power of the operators, from the loosest to the tightest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Precedence {
+    Comma,
+    Assignment,
+    Conditional,
+    Equality,
+    Comparison,
+    Range,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
+    Term,
+    Factor,
+    Unary,
+    Exponent,
+    Postfix,
+}
+
+impl Precedence {
+    fn next(self) -> Precedence {
+        match self {
+            Precedence::Comma => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Conditional,
+            Precedence::Conditional => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Range,
+            Precedence::Range => Precedence::BitOr,
+            Precedence::BitOr => Precedence::BitXor,
+            Precedence::BitXor => Precedence::BitAnd,
+            Precedence::BitAnd => Precedence::Shift,
+            Precedence::Shift => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Exponent,
+            Precedence::Exponent => Precedence::Postfix,
+            Precedence::Postfix => Precedence::Postfix,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Associativity {
+    Left,
+    Right,
+    // Can't be chained: `a..b..c` is an error.
+    None,
+}
+
+// What to build once an infix operator has been found. Prefix and postfix operators only use it to
+// tell the ones assigning to their operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InfixKind {
+    Binary,
+    // `?` expression `:` operand
+    Conditional,
+    // `[` expression `]`
+    Index,
+    // Only valid after an index
+    Assign,
+}
+
+struct Operator {
+    token_type: TokenType,
+    precedence: Precedence,
+    associativity: Associativity,
+    kind: InfixKind,
+}
+
+impl Operator {
+    const fn new(token_type: TokenType, precedence: Precedence, associativity: Associativity) -> Self {
+        Operator { token_type, precedence, associativity, kind: InfixKind::Binary }
+    }
+
+    const fn conditional(token_type: TokenType, precedence: Precedence) -> Self {
+        Operator { token_type, precedence, associativity: Associativity::Right, kind: InfixKind::Conditional }
+    }
+
+    const fn index(token_type: TokenType, precedence: Precedence) -> Self {
+        Operator { token_type, precedence, associativity: Associativity::Left, kind: InfixKind::Index }
+    }
+
+    const fn assign(token_type: TokenType, precedence: Precedence) -> Self {
+        Operator { token_type, precedence, associativity: Associativity::Right, kind: InfixKind::Assign }
+    }
+
+    // `++` and `--`, either before or after their operand
+    const fn increment(token_type: TokenType) -> Self {
+        Operator::assign(token_type, Precedence::Postfix)
+    }
+
+    // The minimum precedence of the operand to the right of this operator. For left-associative
+    // operators, anything binding as loosely as the operator itself is left for the enclosing loop,
+    // so `1 - 2 - 3` groups as `(1 - 2) - 3`.
+    fn right_precedence(&self) -> Precedence {
+        match self.associativity {
+            Associativity::Left | Associativity::None => self.precedence.next(),
+            Associativity::Right => self.precedence,
+        }
+    }
+}
+
+// The operator tables. Adding an operator to the language is a matter of adding its token here.
+// Prefix operators produce ExprKind::Unary and postfix ones ExprKind::Postfix. Infix operators
+// produce whatever their kind says. A token may be both a prefix and an infix operator, like '-'.
+const PREFIX_OPERATORS: &[Operator] = &[
+    Operator::new(TokenType::BANG, Precedence::Unary, Associativity::Right),
+    Operator::new(TokenType::MINUS, Precedence::Unary, Associativity::Right),
+    Operator::new(TokenType::TILDE, Precedence::Unary, Associativity::Right),
+    // The operand binds as tightly as an index, so `++a[0] ** 2` is `(++a[0]) ** 2`.
+    Operator::increment(TokenType::PLUS_PLUS),
+    Operator::increment(TokenType::MINUS_MINUS),
+];
+
+const INFIX_OPERATORS: &[Operator] = &[
+    // The comma operator evaluates both operands and yields the right one, just like in C.
+    Operator::new(TokenType::COMMA, Precedence::Comma, Associativity::Left),
+    Operator::assign(TokenType::EQUAL, Precedence::Assignment),
+    Operator::assign(TokenType::PLUS_EQUAL, Precedence::Assignment),
+    Operator::assign(TokenType::MINUS_EQUAL, Precedence::Assignment),
+    Operator::assign(TokenType::STAR_EQUAL, Precedence::Assignment),
+    Operator::assign(TokenType::SLASH_EQUAL, Precedence::Assignment),
+    Operator::assign(TokenType::PERCENT_EQUAL, Precedence::Assignment),
+    Operator::conditional(TokenType::QUESTION, Precedence::Conditional),
+    Operator::new(TokenType::BANG_EQUAL, Precedence::Equality, Associativity::Left),
+    Operator::new(TokenType::EQUAL_EQUAL, Precedence::Equality, Associativity::Left),
+    Operator::new(TokenType::GREATER, Precedence::Comparison, Associativity::Left),
+    Operator::new(TokenType::GREATER_EQUAL, Precedence::Comparison, Associativity::Left),
+    Operator::new(TokenType::LESS, Precedence::Comparison, Associativity::Left),
+    Operator::new(TokenType::LESS_EQUAL, Precedence::Comparison, Associativity::Left),
+    // `a..b` excludes b, `a..=b` includes it.
+    Operator::new(TokenType::DOT_DOT, Precedence::Range, Associativity::None),
+    Operator::new(TokenType::DOT_DOT_EQUAL, Precedence::Range, Associativity::None),
+    // Unlike in C, the bitwise operators bind tighter than the comparisons, so `flags & MASK == 0`
+    // means `(flags & MASK) == 0`. Python does the same.
+    Operator::new(TokenType::PIPE, Precedence::BitOr, Associativity::Left),
+    Operator::new(TokenType::CARET, Precedence::BitXor, Associativity::Left),
+    Operator::new(TokenType::AMPERSAND, Precedence::BitAnd, Associativity::Left),
+    Operator::new(TokenType::LESS_LESS, Precedence::Shift, Associativity::Left),
+    Operator::new(TokenType::GREATER_GREATER, Precedence::Shift, Associativity::Left),
+    Operator::new(TokenType::MINUS, Precedence::Term, Associativity::Left),
+    Operator::new(TokenType::PLUS, Precedence::Term, Associativity::Left),
+    Operator::new(TokenType::SLASH, Precedence::Factor, Associativity::Left),
+    Operator::new(TokenType::STAR, Precedence::Factor, Associativity::Left),
+    Operator::new(TokenType::PERCENT, Precedence::Factor, Associativity::Left),
+    Operator::new(TokenType::TILDE_SLASH, Precedence::Factor, Associativity::Left),
+    // Binds tighter than a prefix operator on its left, so `-2 ** 2` is `-(2 ** 2)`, as in math
+    // and Python. The right operand is parsed like any other, so `2 ** -1` works too.
+    Operator::new(TokenType::STAR_STAR, Precedence::Exponent, Associativity::Right),
+    Operator::index(TokenType::LEFT_BRACKET, Precedence::Postfix),
+];
+
+const POSTFIX_OPERATORS: &[Operator] = &[
+    Operator::increment(TokenType::PLUS_PLUS),
+    Operator::increment(TokenType::MINUS_MINUS),
+];
+
+pub(crate) fn infix_precedence(token_type: TokenType) -> Option<Precedence> {
+    find_operator(INFIX_OPERATORS, token_type).map(|operator| operator.precedence)
+}
+
+fn is_operator(token_type: TokenType) -> bool {
+    find_operator(INFIX_OPERATORS, token_type).is_some() || find_operator(POSTFIX_OPERATORS, token_type).is_some()
+}
+
+// Tokens that end the expression they follow: closing brackets, ':' and the end of the input.
+fn is_closing(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::RIGHT_PAREN | TokenType::RIGHT_BRACKET | TokenType::RIGHT_BRACE | TokenType::COLON | TokenType::EOF
+    )
+}
+
+fn can_start_expression(token_type: TokenType) -> bool {
+    find_operator(PREFIX_OPERATORS, token_type).is_some()
+        || matches!(
+            token_type,
+            TokenType::FALSE | TokenType::TRUE | TokenType::NIL | TokenType::NUMBER | TokenType::STRING | TokenType::LEFT_PAREN
+                | TokenType::LEFT_BRACKET
+                | TokenType::LEFT_BRACE
+        )
+}
+
+fn find_operator(table: &'static [Operator], token_type: TokenType) -> Option<&'static Operator> {
+    table.iter().find(|operator| operator.token_type == token_type)
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[line {}] Error{}: {}", self.line, self.location, self.message)
+    }
+}
+
+// How the parser turns what it recognizes into tree nodes. The default, BoxBuilder, builds the
+// usual boxed Expr tree; the one behind arena::ExprArena::parse() stores the nodes in a vector. Like the parser
+// itself, builders get &self and rely on interior mutability.
+//
+// Tokens are given along with their index in the token vector the parser was created with.
+pub trait TreeBuilder {
+    type Node;
+
+    fn binary(&self, left: Self::Node, operator: &Token, operator_index: usize, right: Self::Node) -> Self::Node;
+    fn conditional(&self, condition: Self::Node, then_branch: Self::Node, else_branch: Self::Node) -> Self::Node;
+    // `tokens` is never empty. It starts at index `first_token_index`.
+    fn error(&self, expression: Option<Self::Node>, tokens: &[Token], first_token_index: usize) -> Self::Node;
+    // `span` covers the parentheses as well
+    fn grouping(&self, span: Span, expression: Self::Node) -> Self::Node;
+    // `closing` is the span of the ']'
+    fn index(&self, object: Self::Node, index: Self::Node, closing: Span) -> Self::Node;
+    // The element is never built as an index node first. `closing` is the span of the ']'.
+    fn index_assign(&self, object: Self::Node, index: Self::Node, closing: Span, operator: &Token, operator_index: usize, value: Self::Node) -> Self::Node;
+    // `span` covers the brackets as well
+    fn list(&self, span: Span, elements: Vec<Self::Node>) -> Self::Node;
+    fn literal(&self, token: &Token, token_index: usize) -> Self::Node;
+    // `span` covers the braces as well
+    fn map(&self, span: Span, entries: Vec<(Self::Node, Self::Node)>) -> Self::Node;
+    // `position` is the byte offset where the operand should have been.
+    fn missing(&self, position: usize) -> Self::Node;
+    fn postfix(&self, left: Self::Node, operator: &Token, operator_index: usize) -> Self::Node;
+    fn unary(&self, operator: &Token, operator_index: usize, right: Self::Node) -> Self::Node;
+}
+
+#[derive(Default)]
+pub struct BoxBuilder {
+    next_id: Cell<u32>,
+}
+
+impl BoxBuilder {
+    fn make_expr(&self, span: Span, kind: ExprKind) -> Expr {
+        let id = NodeId(self.next_id.get());
+        self.next_id.set(id.0 + 1);
+        Expr { id, span, kind }
+    }
+}
+
+impl TreeBuilder for BoxBuilder {
+    type Node = Expr;
+
+    fn binary(&self, left: Expr, operator: &Token, _operator_index: usize, right: Expr) -> Expr {
+        let span = left.span.to(right.span);
+        self.make_expr(span, ExprKind::Binary { left: Box::new(left), operator: operator.clone(), right: Box::new(right) })
+    }
+
+    fn conditional(&self, condition: Expr, then_branch: Expr, else_branch: Expr) -> Expr {
+        let span = condition.span.to(else_branch.span);
+        let (condition, then_branch, else_branch) = (Box::new(condition), Box::new(then_branch), Box::new(else_branch));
+        self.make_expr(span, ExprKind::Conditional { condition, then_branch, else_branch })
+    }
+
+    fn error(&self, expression: Option<Expr>, tokens: &[Token], _first_token_index: usize) -> Expr {
+        let mut span = tokens[0].span.to(tokens[tokens.len() - 1].span);
+        if let Some(expression) = &expression {
+            span = expression.span.to(span);
+        }
+        self.make_expr(span, ExprKind::Error { expression: expression.map(Box::new), tokens: tokens.to_vec() })
+    }
+
+    fn grouping(&self, span: Span, expression: Expr) -> Expr {
+        self.make_expr(span, ExprKind::Grouping { expression: Box::new(expression) })
+    }
+
+    fn index(&self, object: Expr, index: Expr, closing: Span) -> Expr {
+        let span = object.span.to(closing);
+        self.make_expr(span, ExprKind::Index { object: Box::new(object), index: Box::new(index) })
+    }
+
+    fn index_assign(&self, object: Expr, index: Expr, closing: Span, operator: &Token, _operator_index: usize, value: Expr) -> Expr {
+        let span = object.span.to(value.span);
+        let (object, index, value) = (Box::new(object), Box::new(index), Box::new(value));
+        self.make_expr(span, ExprKind::IndexAssign { object, index, closing, operator: operator.clone(), value })
+    }
+
+    fn list(&self, span: Span, elements: Vec<Expr>) -> Expr {
+        self.make_expr(span, ExprKind::List { elements })
+    }
+
+    fn literal(&self, token: &Token, _token_index: usize) -> Expr {
+        self.make_expr(token.span, ExprKind::Literal { value: token.literal.clone() })
+    }
+
+    fn map(&self, span: Span, entries: Vec<(Expr, Expr)>) -> Expr {
+        self.make_expr(span, ExprKind::Map { entries })
+    }
+
+    fn missing(&self, position: usize) -> Expr {
+        self.make_expr(Span::new(position, position), ExprKind::Missing)
+    }
+
+    fn postfix(&self, left: Expr, operator: &Token, _operator_index: usize) -> Expr {
+        let span = left.span.to(operator.span);
+        self.make_expr(span, ExprKind::Postfix { left: Box::new(left), operator: operator.clone() })
+    }
+
+    fn unary(&self, operator: &Token, _operator_index: usize, right: Expr) -> Expr {
+        let span = operator.span.to(right.span);
+        self.make_expr(span, ExprKind::Unary { operator: operator.clone(), right: Box::new(right) })
+    }
 }
 
-pub struct Parser {
+pub struct Parser<B: TreeBuilder = BoxBuilder> {
     // We use a Cell here for interior mutability. Since the parsing method calls are recursive and
     // calling each other, all of them would need to borrow self butably just so one method could
     // increment the current Token pointer. Let's do it with a Cell here.
     current: Cell<usize>,
-    tokens: Vec<Token>
+    tokens: Vec<Token>,
+    // Same reasoning as above: error productions record their errors here and keep parsing.
+    errors: RefCell<Vec<ParseError>>,
+    // Set by parse_tolerant().
+    tolerant: Cell<bool>,
+    builder: B,
 }
 
 impl Parser {
-    fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Parser::with_builder(tokens, BoxBuilder::default())
+    }
+}
+
+impl<B: TreeBuilder> Parser<B> {
+    pub fn with_builder(tokens: Vec<Token>, builder: B) -> Self {
         Parser {
             current: Cell::new(0),
-            tokens
+            tokens,
+            errors: RefCell::new(Vec::new()),
+            tolerant: Cell::new(false),
+            builder,
         }
     }
 
-    fn expression(&self) -> Expr {
-        self.equality()
+    // Returns None if any error was found. The errors themselves are available through errors().
+    pub fn parse(&self) -> Option<B::Node> {
+        let result = self.expression().and_then(|expr| {
+            if !self.is_at_end() {
+                return Err(self.error(self.peek(), "Expect end of expression."));
+            }
+            Ok(expr)
+        });
+        match result {
+            Ok(expr) if self.errors.borrow().is_empty() => Some(expr),
+            Ok(_) => None,
+            Err(error) => {
+                self.record(error);
+                None
+            }
+        }
     }
 
-    fn equality(&self) -> Expr {
-        let mut expr: Expr = self.comparison();
-
-        while self.match_token(vec!(TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL)) {
-            let operator: &Token = self.previous();
-            let right: Box<Expr> = Box::new(self.comparison());
-            let left: Box<Expr> = Box::new(expr);
-            expr = Expr::Binary { left, operator, right };
+    // For editors, which need a tree even while the code is being typed. This never gives up:
+    // missing operands become ExprKind::Missing placeholders, missing ')' and ':' are assumed, and
+    // unexpected tokens end up in ExprKind::Error nodes. The errors are still available through
+    // errors(), so the tree is complete but not necessarily valid.
+    pub fn parse_tolerant(&self) -> B::Node {
+        self.tolerant.set(true);
+        let expr: B::Node = self.expression().expect("the tolerant parser never fails");
+        if self.is_at_end() {
+            return expr;
         }
+        // A stray closing bracket or ':'. Nothing else stops the expression before the end.
+        self.record(self.error(self.peek(), "Expect end of expression."));
+        let start: usize = self.current.get();
+        while !self.is_at_end() {
+            self.advance();
+        }
+        self.builder.error(Some(expr), &self.tokens[start..self.current.get()], start)
+    }
 
-        return expr
+    pub fn errors(&self) -> Ref<'_, Vec<ParseError>> {
+        self.errors.borrow()
     }
 
-    fn comparison(&self) -> Expr {
-        let mut expr: Expr = self.term();
+    // Gives back the tokens, the builder and the errors, for builders whose nodes refer to tokens
+    // by index.
+    pub fn into_parts(self) -> (Vec<Token>, B, Vec<ParseError>) {
+        (self.tokens, self.builder, self.errors.into_inner())
+    }
 
-        while self.match_token(vec!(TokenType::GREATER, TokenType::GREATER_EQUAL, TokenType::LESS, TokenType::LESS_EQUAL)) {
-            let operator: &Token = self.previous();
-            let right: Box<Expr> = Box::new(self.term());
-            let left: Box<Expr> = Box::new(expr);
-            expr = Expr::Binary { left, operator, right };
-        }
+    fn expression(&self) -> Result<B::Node, ParseError> {
+        self.parse_precedence(Precedence::Comma)
+    }
 
-        return expr
+    // Pratt parser: parse a prefix expression, then keep folding it into the postfix and infix
+    // operators that follow, as long as they bind at least as tightly as `min_precedence`.
+    fn parse_precedence(&self, min_precedence: Precedence) -> Result<B::Node, ParseError> {
+        Ok(self.parse_target(min_precedence)?.0)
     }
 
-    fn term(&self) -> Expr {
-        let mut expr: Expr = self.factor();
+    // Same as parse_precedence(), along with whether the expression can be assigned to, which
+    // only an index can.
+    fn parse_target(&self, min_precedence: Precedence) -> Result<(B::Node, bool), ParseError> {
+        let mut expr: B::Node = self.prefix()?;
+        let mut assignable: bool = false;
 
-        while self.match_token(vec!(TokenType::MINUS, TokenType::PLUS)) {
-            let operator: &Token = self.previous();
-            let right: Box<Expr> = Box::new(self.factor());
-            let left: Box<Expr> = Box::new(expr);
-            expr = Expr::Binary { left, operator, right };
+        loop {
+            let token_type: TokenType = self.peek().token_type;
+            if let Some(operator) = find_operator(POSTFIX_OPERATORS, token_type) {
+                if operator.precedence < min_precedence {
+                    break;
+                }
+                self.advance();
+                if operator.kind == InfixKind::Assign && !assignable {
+                    self.record(self.error(self.previous(), "Invalid assignment target."));
+                }
+                expr = self.builder.postfix(expr, self.previous(), self.previous_index());
+                assignable = false;
+            } else if let Some(operator) = find_operator(INFIX_OPERATORS, token_type) {
+                if operator.precedence < min_precedence {
+                    break;
+                }
+                self.advance();
+                (expr, assignable) = self.infix(expr, operator, min_precedence)?;
+            } else if self.tolerant.get() && !is_closing(token_type) {
+                // Only an operator can follow an expression, so in tolerant mode whatever does is
+                // put in an error node, along with that expression.
+                self.record(self.error(self.peek(), "Expect end of expression."));
+                let start: usize = self.skip_while(|token_type| !is_operator(token_type) && !is_closing(token_type));
+                expr = self.builder.error(Some(expr), &self.tokens[start..self.current.get()], start);
+                assignable = false;
+            } else {
+                break;
+            }
         }
 
-        return expr
+        Ok((expr, assignable))
     }
 
-    fn factor(&self) -> Expr {
-        let mut expr: Expr = self.unary();
+    fn prefix(&self) -> Result<B::Node, ParseError> {
+        let token_type: TokenType = self.peek().token_type;
+        if let Some(operator) = find_operator(PREFIX_OPERATORS, token_type) {
+            self.advance();
+            let operator_index: usize = self.previous_index();
+            let (right, assignable) = self.parse_target(operator.precedence)?;
+            if operator.kind == InfixKind::Assign && !assignable {
+                self.record(self.error(&self.tokens[operator_index], "Invalid assignment target."));
+            }
+            return Ok(self.builder.unary(&self.tokens[operator_index], operator_index, right));
+        }
 
-        while self.match_token(vec!(TokenType::SLASH, TokenType::STAR)) {
-            let operator: &Token = self.previous();
-            let right: Box<Expr> = Box::new(self.unary());
-            let left: Box<Expr> = Box::new(expr);
-            expr = Expr::Binary { left, operator, right };
+        // Error production: a binary operator with no left operand. We report it, then parse the
+        // right operand at the operator's own precedence so that any errors in there are found as
+        // well. A placeholder takes the place of the left operand, which lets the enclosing loops
+        // carry on parsing. Operators that are also prefix operators (like '-') never get here.
+        if let Some(operator) = find_operator(INFIX_OPERATORS, token_type) {
+            if operator.kind == InfixKind::Binary {
+                self.advance();
+                let operator_index: usize = self.previous_index();
+                let operator_token: &Token = &self.tokens[operator_index];
+                self.record(self.error(operator_token, &format!("Missing left-hand operand for '{}'", operator_token.lexeme)));
+                let left: B::Node = self.builder.missing(operator_token.span.start);
+                let right: B::Node = self.parse_precedence(operator.right_precedence())?;
+                return Ok(self.builder.binary(left, operator_token, operator_index, right));
+            }
         }
 
-        return expr
+        self.primary()
     }
 
-    fn unary(&self) -> Expr {
-        if self.match_token(vec!(TokenType::BANG, TokenType::MINUS)) {
-            let operator: &Token = self.previous();
-            let right: Box<Expr> = Box::new(self.unary());
-            return Expr::Unary { operator, right };
+    // Called with the operator token already consumed. Also returns whether the result can be
+    // assigned to, as parse_target() does.
+    fn infix(&self, left: B::Node, operator: &Operator, min_precedence: Precedence) -> Result<(B::Node, bool), ParseError> {
+        let operator_index: usize = self.previous_index();
+        match operator.kind {
+            InfixKind::Binary => {
+                let right: B::Node = self.parse_precedence(operator.right_precedence())?;
+                // The enclosing loop would carry on as if the operator was left-associative, which
+                // is as good a guess as any once the error is recorded.
+                if operator.associativity == Associativity::None && infix_precedence(self.peek().token_type) == Some(operator.precedence) {
+                    self.record(self.error(self.peek(), &format!("Can't chain '{}' without parentheses.", self.peek().lexeme)));
+                }
+                Ok((self.builder.binary(left, &self.tokens[operator_index], operator_index, right), false))
+            }
+            // As in C, anything can go between '?' and ':', including a comma expression.
+            InfixKind::Conditional => {
+                let then_branch: B::Node = self.expression()?;
+                let colon = self.consume_or_recover(TokenType::COLON, "Expect ':' after then branch of conditional expression.")?;
+                let else_branch: B::Node = match colon {
+                    Some(_) => self.parse_precedence(operator.right_precedence())?,
+                    None => self.builder.missing(self.peek().span.start),
+                };
+                Ok((self.builder.conditional(left, then_branch, else_branch), false))
+            }
+            InfixKind::Index => {
+                let index: B::Node = self.expression()?;
+                let closing: Span = match self.consume_or_recover(TokenType::RIGHT_BRACKET, "Expect ']' after index.")? {
+                    Some(token) => token.span,
+                    None => self.previous().span,
+                };
+                // An assignment to the element is taken right away, so that the builder gets the
+                // object and the index rather than an index node it would have to take apart.
+                if let Some(assign) = find_operator(INFIX_OPERATORS, self.peek().token_type) {
+                    if assign.kind == InfixKind::Assign && assign.precedence >= min_precedence {
+                        self.advance();
+                        let operator_index: usize = self.previous_index();
+                        let value: B::Node = self.parse_precedence(assign.right_precedence())?;
+                        return Ok((self.builder.index_assign(left, index, closing, &self.tokens[operator_index], operator_index, value), false));
+                    }
+                }
+                Ok((self.builder.index(left, index, closing), true))
+            }
+            // Assignments to an index never get here, so this one has an invalid target. As in
+            // the book, that is reported but doesn't stop the parser, which has nothing to
+            // synchronize on anyway. The value is parsed for its own errors, then kept in an error
+            // node along with the operator, so that no token goes missing from the tree.
+            InfixKind::Assign => {
+                self.parse_precedence(operator.right_precedence())?;
+                self.record(self.error(&self.tokens[operator_index], "Invalid assignment target."));
+                Ok((self.builder.error(Some(left), &self.tokens[operator_index..self.current.get()], operator_index), false))
+            }
         }
-        self.primary()
     }
 
-    fn primary(&self) -> Expr {
+    fn primary(&self) -> Result<B::Node, ParseError> {
         // Instead of returning booleans for true and false, and None for nil, as suggested by the
         // book, we changed the lexer code to include the literal values in the boolean tokens TRUE
-        // and FALSE so we can just pass a reference to those here, avoiding issues with the borrow
-        // checker when passing the values for STRING and NUMBER.
+        // and FALSE so the builder can just take them from the token.
         // As an consequence, we do not need to have several if clauses here as we have in the
         // book.
-        if self.match_token(vec!(TokenType::FALSE, TokenType::TRUE, TokenType::NIL, TokenType::NUMBER, TokenType::STRING)) {
-            return Expr::Literal { value: &self.previous().literal };
+        if self.match_token(&[TokenType::FALSE, TokenType::TRUE, TokenType::NIL, TokenType::NUMBER, TokenType::STRING]) {
+            return Ok(self.builder.literal(self.previous(), self.previous_index()));
+        }
+        if self.match_token(&[TokenType::LEFT_PAREN]) {
+            let start: Span = self.previous().span;
+            let expr: B::Node = self.expression()?;
+            let end: Span = match self.consume_or_recover(TokenType::RIGHT_PAREN, "Expect ')' after expression.")? {
+                Some(token) => token.span,
+                None => self.previous().span,
+            };
+            return Ok(self.builder.grouping(start.to(end), expr));
         }
-        if self.match_token(vec!(TokenType::LEFT_PAREN)) {
-            let expr: Expr = self.expression();
-            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.");
-            return Expr::Grouping { expression: Box::new(expr) };
+        // Elements can't be comma expressions, since the comma separates them.
+        if self.match_token(&[TokenType::LEFT_BRACKET]) {
+            let start: Span = self.previous().span;
+            let mut elements: Vec<B::Node> = Vec::new();
+            if !self.check(TokenType::RIGHT_BRACKET) {
+                loop {
+                    elements.push(self.parse_precedence(Precedence::Assignment)?);
+                    if !self.match_token(&[TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            let end: Span = match self.consume_or_recover(TokenType::RIGHT_BRACKET, "Expect ']' after list elements.")? {
+                Some(token) => token.span,
+                None => self.previous().span,
+            };
+            return Ok(self.builder.list(start.to(end), elements));
+        }
+        // Maps and blocks both start with '{'. There are no statements yet, so in an expression a
+        // '{' is always a map. Once blocks come, a statement starting with '{' will be a block,
+        // as in JavaScript. Keys are parsed like list elements, and a ':' right after one ends it
+        // unless it belongs to a conditional in the key.
+        if self.match_token(&[TokenType::LEFT_BRACE]) {
+            let start: Span = self.previous().span;
+            let mut entries: Vec<(B::Node, B::Node)> = Vec::new();
+            if !self.check(TokenType::RIGHT_BRACE) {
+                loop {
+                    let key: B::Node = self.parse_precedence(Precedence::Assignment)?;
+                    let value: B::Node = match self.consume_or_recover(TokenType::COLON, "Expect ':' after map key.")? {
+                        Some(_) => self.parse_precedence(Precedence::Assignment)?,
+                        None => self.builder.missing(self.peek().span.start),
+                    };
+                    entries.push((key, value));
+                    if !self.match_token(&[TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            let end: Span = match self.consume_or_recover(TokenType::RIGHT_BRACE, "Expect '}' after map entries.")? {
+                Some(token) => token.span,
+                None => self.previous().span,
+            };
+            return Ok(self.builder.map(start.to(end), entries));
+        }
+
+        let error = self.error(self.peek(), "Expect expression.");
+        if !self.tolerant.get() {
+            return Err(error);
         }
-        // TODO: improve error handling. We should never get here, if we do, should we really
-        // panic?
-        panic!()
+        // Tokens that can't start an expression are skipped, up to something that makes sense
+        // here. If there are none, the operand is just missing.
+        self.record(error);
+        let start: usize = self.skip_while(|token_type| {
+            !can_start_expression(token_type) && !is_operator(token_type) && !is_closing(token_type)
+        });
+        if start == self.current.get() {
+            return Ok(self.builder.missing(self.peek().span.start));
+        }
+        Ok(self.builder.error(None, &self.tokens[start..self.current.get()], start))
+    }
+
+    fn consume(&self, token_type: TokenType, err_msg: &str) -> Result<&Token, ParseError> {
+        if self.check(token_type) {
+            return Ok(self.advance());
+        }
+        Err(self.error(self.peek(), err_msg))
     }
 
-    fn consume(&self, token_type: TokenType, err_msg: &str) {
-        panic!()
+    // consume(), except that in tolerant mode a missing token is only recorded as an error and
+    // parsing goes on as if it had been there.
+    fn consume_or_recover(&self, token_type: TokenType, err_msg: &str) -> Result<Option<&Token>, ParseError> {
+        match self.consume(token_type, err_msg) {
+            Ok(token) => Ok(Some(token)),
+            Err(error) if self.tolerant.get() => {
+                self.record(error);
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    // Advances past the tokens for which `predicate` holds, and returns the index of the first one.
+    fn skip_while(&self, predicate: impl Fn(TokenType) -> bool) -> usize {
+        let start: usize = self.current.get();
+        while !self.is_at_end() && predicate(self.peek().token_type) {
+            self.advance();
+        }
+        start
+    }
+
+    // For errors that don't stop the parser.
+    fn record(&self, error: ParseError) {
+        self.errors.borrow_mut().push(error);
+    }
+
+    fn error(&self, token: &Token, message: &str) -> ParseError {
+        let location = if token.token_type == TokenType::EOF {
+            String::from(" at end")
+        } else {
+            format!(" at '{}'", token.lexeme)
+        };
+        ParseError { line: token.line, location, message: String::from(message) }
     }
 
     // match is a reserved keyword. Hence, let's call this function match_token
-    fn match_token(&self, token_types: Vec<TokenType>) -> bool {
-        let matches: bool = token_types.into_iter().any(|t| self.check(t));
+    fn match_token(&self, token_types: &[TokenType]) -> bool {
+        let matches: bool = token_types.iter().any(|t| self.check(*t));
         if matches {
             self.advance();
         }
@@ -148,38 +716,109 @@ impl Parser {
     }
 
     fn previous(&self) -> &Token {
-        &self.tokens[self.current.get() - 1]
+        &self.tokens[self.previous_index()]
+    }
+
+    fn previous_index(&self) -> usize {
+        self.current.get() - 1
+    }
+}
+
+fn literal_to_string(value: &Literal) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => String::from("nil"),
     }
 }
 
 pub struct AstPrinter;
 
 impl AstPrinter {
-    fn parenthesize(&self, name: &str, exprs: Vec<&Expr>) -> String {
-        let mut ast_str = format!("({name}");
+    pub fn print(&self, expr: &Expr) -> String {
+        let mut writer = AstWriter { output: String::new() };
+        writer.visit_expr(expr);
+        writer.output
+    }
+}
+
+struct AstWriter {
+    output: String,
+}
+
+impl AstWriter {
+    fn parenthesize(&mut self, name: &str, exprs: &[&Expr]) {
+        self.output.push('(');
+        self.output.push_str(name);
         for expr in exprs {
-            ast_str.push_str(" ");
-            ast_str.push_str(&self.print(&expr));
+            self.output.push(' ');
+            self.visit_expr(expr);
         }
-        ast_str.push_str(")");
-        ast_str
+        self.output.push(')');
     }
+}
 
-    pub fn print(&self, expr: &Expr) -> String {
-        match expr {
-            Expr::Binary { left, operator, right } =>
-                self.parenthesize(&operator.lexeme, vec![&left, &right]),
-            Expr::Grouping { expression } =>
-                self.parenthesize(&String::from("group"), vec![&expression]),
-            Expr::Literal { value } => {
-                if value.is_none() {
-                    return String::from("nil")
-                }
-                value.as_ref().unwrap().to_string()
-            }
-            Expr::Unary { operator, right } =>
-                self.parenthesize(&operator.lexeme, vec![&right]),
+impl Visitor for AstWriter {
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        self.parenthesize(&operator.lexeme, &[left, right]);
+    }
+
+    fn visit_conditional(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) {
+        self.parenthesize("?:", &[condition, then_branch, else_branch]);
+    }
+
+    fn visit_error(&mut self, expression: Option<&Expr>, tokens: &[Token]) {
+        self.output.push_str("(error");
+        if let Some(expression) = expression {
+            self.output.push(' ');
+            self.visit_expr(expression);
+        }
+        for token in tokens {
+            self.output.push(' ');
+            self.output.push_str(&token.lexeme);
         }
+        self.output.push(')');
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) {
+        self.parenthesize("group", &[expression]);
+    }
+
+    fn visit_index(&mut self, object: &Expr, index: &Expr) {
+        self.parenthesize("[]", &[object, index]);
+    }
+
+    fn visit_index_assign(&mut self, object: &Expr, index: &Expr, operator: &Token, value: &Expr) {
+        self.parenthesize(&format!("[]{}", operator.lexeme), &[object, index, value]);
+    }
+
+    fn visit_list(&mut self, elements: &[Expr]) {
+        self.parenthesize("list", &elements.iter().collect::<Vec<&Expr>>());
+    }
+
+    fn visit_literal(&mut self, value: &Literal) {
+        self.output.push_str(&literal_to_string(value));
+    }
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)]) {
+        let exprs: Vec<&Expr> = entries.iter().flat_map(|(key, value)| [key, value]).collect();
+        self.parenthesize("map", &exprs);
+    }
+
+    fn visit_missing(&mut self) {
+        self.output.push_str("<missing>");
+    }
+
+    // The operator goes last so postfix and prefix operators sharing a lexeme can be told apart.
+    fn visit_postfix(&mut self, left: &Expr, operator: &Token) {
+        self.output.push('(');
+        self.visit_expr(left);
+        self.output.push(' ');
+        self.output.push_str(&operator.lexeme);
+        self.output.push(')');
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) {
+        self.parenthesize(&operator.lexeme, &[right]);
     }
 }
 
@@ -187,18 +826,178 @@ pub struct RpnPrinter;
 
 impl RpnPrinter {
     pub fn print(&self, expr: &Expr) -> String {
-        match expr {
-            Expr::Binary { left, operator, right } => format!("{} {} {}", self.print(&left), self.print(&right), &operator.lexeme),
-            Expr::Grouping { expression } => {
-                format!("{}", self.print(&expression))
-            }
-            Expr::Literal { value } => {
-                if value.is_none() {
-                    return String::from("nil")
-                }
-                value.as_ref().unwrap().to_string()
-            }
-            Expr::Unary { operator, right } => format!("{} {}", self.print(&right), &operator.lexeme)
+        let mut writer = RpnWriter { words: Vec::new() };
+        writer.visit_expr(expr);
+        writer.words.join(" ")
+    }
+}
+
+// Operands are visited before their operator, which is exactly reverse polish notation. Groupings
+// need nothing special: the default walk just visits the grouped expression.
+struct RpnWriter {
+    words: Vec<String>,
+}
+
+impl Visitor for RpnWriter {
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+        self.words.push(operator.lexeme.clone());
+    }
+
+    fn visit_conditional(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) {
+        walk_conditional(self, condition, then_branch, else_branch);
+        self.words.push(String::from("?:"));
+    }
+
+    fn visit_index(&mut self, object: &Expr, index: &Expr) {
+        walk_index(self, object, index);
+        self.words.push(String::from("[]"));
+    }
+
+    fn visit_index_assign(&mut self, object: &Expr, index: &Expr, operator: &Token, value: &Expr) {
+        self.visit_expr(object);
+        self.visit_expr(index);
+        self.visit_expr(value);
+        self.words.push(format!("[]{}", operator.lexeme));
+    }
+
+    // The number of elements goes with the operator, so the list can be told from its elements.
+    fn visit_list(&mut self, elements: &[Expr]) {
+        walk_list(self, elements);
+        self.words.push(format!("list/{}", elements.len()));
+    }
+
+    // The unexpected tokens are not operators, so they are written as they came.
+    fn visit_error(&mut self, expression: Option<&Expr>, tokens: &[Token]) {
+        if let Some(expression) = expression {
+            self.visit_expr(expression);
         }
+        self.words.extend(tokens.iter().map(|token| token.lexeme.clone()));
+        self.words.push(String::from("error"));
+    }
+
+    fn visit_literal(&mut self, value: &Literal) {
+        self.words.push(literal_to_string(value));
+    }
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)]) {
+        walk_map(self, entries);
+        self.words.push(format!("map/{}", entries.len()));
+    }
+
+    fn visit_missing(&mut self) {
+        self.words.push(String::from("<missing>"));
+    }
+
+    fn visit_postfix(&mut self, left: &Expr, operator: &Token) {
+        self.visit_expr(left);
+        self.words.push(operator.lexeme.clone());
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) {
+        self.visit_expr(right);
+        self.words.push(operator.lexeme.clone());
+    }
+}
+
+// Renders the tree as a Graphviz graph, with one node per expression labelled by its operator or
+// value. Try it with `yarli --ast-dot script.lox | dot -Tsvg > ast.svg`.
+pub struct DotPrinter;
+
+impl DotPrinter {
+    pub fn print(&self, expr: &Expr) -> String {
+        let mut writer = DotWriter { lines: Vec::new(), parents: Vec::new() };
+        writer.visit_expr(expr);
+        // ordering=out keeps the children in source order, so left operands are drawn on the left.
+        format!("digraph ast {{\n    ordering=out;\n    node [shape=box];\n{}\n}}", writer.lines.join("\n"))
+    }
+}
+
+struct DotWriter {
+    lines: Vec<String>,
+    // The nodes we are in, the innermost last.
+    parents: Vec<NodeId>,
+}
+
+impl DotWriter {
+    // Declares the node being visited.
+    fn node(&mut self, label: &str) {
+        let id = self.parents.last().unwrap().0;
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        self.lines.push(format!("    n{id} [label=\"{label}\"];"));
+    }
+}
+
+impl Visitor for DotWriter {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Some(parent) = self.parents.last() {
+            self.lines.push(format!("    n{} -> n{};", parent.0, expr.id.0));
+        }
+        self.parents.push(expr.id);
+        walk_expr(self, expr);
+        self.parents.pop();
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        self.node(&operator.lexeme);
+        walk_binary(self, left, operator, right);
+    }
+
+    fn visit_conditional(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) {
+        self.node("?:");
+        walk_conditional(self, condition, then_branch, else_branch);
+    }
+
+    fn visit_error(&mut self, expression: Option<&Expr>, tokens: &[Token]) {
+        let lexemes: Vec<&str> = tokens.iter().map(|token| token.lexeme.as_str()).collect();
+        self.node(&format!("error: {}", lexemes.join(" ")));
+        walk_error(self, expression, tokens);
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) {
+        self.node("group");
+        walk_grouping(self, expression);
+    }
+
+    fn visit_index(&mut self, object: &Expr, index: &Expr) {
+        self.node("[]");
+        walk_index(self, object, index);
+    }
+
+    fn visit_index_assign(&mut self, object: &Expr, index: &Expr, operator: &Token, value: &Expr) {
+        self.node(&format!("[]{}", operator.lexeme));
+        walk_index_assign(self, object, index, operator, value);
+    }
+
+    fn visit_list(&mut self, elements: &[Expr]) {
+        self.node("list");
+        walk_list(self, elements);
+    }
+
+    fn visit_literal(&mut self, value: &Literal) {
+        match value {
+            Some(LiteralValue::String(value)) => self.node(&format!("\"{value}\"")),
+            _ => self.node(&literal_to_string(value)),
+        }
+    }
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)]) {
+        self.node("map");
+        walk_map(self, entries);
+    }
+
+    fn visit_missing(&mut self) {
+        self.node("missing");
+    }
+
+    fn visit_postfix(&mut self, left: &Expr, operator: &Token) {
+        self.node(&operator.lexeme);
+        walk_postfix(self, left, operator);
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) {
+        self.node(&operator.lexeme);
+        walk_unary(self, operator, right);
     }
 }
@@ -0,0 +1,284 @@
+use crate::lexer::{Literal, Token};
+use crate::parser::{Expr, ExprKind};
+
+// Traversal protocol for the expression tree. Every method has a default implementation that just
+// walks into the children, so an implementor only overrides the nodes it cares about. An override
+// that still wants the children visited calls the matching walk_* function (or visits them itself,
+// in whatever order it needs).
+//
+// visit_expr() sees every node, including its id and span, before it is dispatched to the
+// per-variant methods. That is the place to fill side tables keyed by NodeId.
+pub trait Visitor: Sized {
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        walk_binary(self, left, operator, right)
+    }
+
+    fn visit_conditional(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) {
+        walk_conditional(self, condition, then_branch, else_branch)
+    }
+
+    fn visit_error(&mut self, expression: Option<&Expr>, tokens: &[Token]) {
+        walk_error(self, expression, tokens)
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) {
+        walk_grouping(self, expression)
+    }
+
+    fn visit_index(&mut self, object: &Expr, index: &Expr) {
+        walk_index(self, object, index)
+    }
+
+    fn visit_index_assign(&mut self, object: &Expr, index: &Expr, operator: &Token, value: &Expr) {
+        walk_index_assign(self, object, index, operator, value)
+    }
+
+    fn visit_list(&mut self, elements: &[Expr]) {
+        walk_list(self, elements)
+    }
+
+    fn visit_literal(&mut self, _value: &Literal) {}
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)]) {
+        walk_map(self, entries)
+    }
+
+    fn visit_missing(&mut self) {}
+
+    fn visit_postfix(&mut self, left: &Expr, operator: &Token) {
+        walk_postfix(self, left, operator)
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) {
+        walk_unary(self, operator, right)
+    }
+
+    fn visit_token(&mut self, _token: &Token) {}
+}
+
+pub fn walk_expr<V: Visitor>(visitor: &mut V, expr: &Expr) {
+    match &expr.kind {
+        ExprKind::Binary { left, operator, right } => visitor.visit_binary(left, operator, right),
+        ExprKind::Conditional { condition, then_branch, else_branch } =>
+            visitor.visit_conditional(condition, then_branch, else_branch),
+        ExprKind::Error { expression, tokens } => visitor.visit_error(expression.as_deref(), tokens),
+        ExprKind::Grouping { expression } => visitor.visit_grouping(expression),
+        ExprKind::Index { object, index } => visitor.visit_index(object, index),
+        ExprKind::IndexAssign { object, index, operator, value, .. } => visitor.visit_index_assign(object, index, operator, value),
+        ExprKind::List { elements } => visitor.visit_list(elements),
+        ExprKind::Literal { value } => visitor.visit_literal(value),
+        ExprKind::Map { entries } => visitor.visit_map(entries),
+        ExprKind::Missing => visitor.visit_missing(),
+        ExprKind::Postfix { left, operator } => visitor.visit_postfix(left, operator),
+        ExprKind::Unary { operator, right } => visitor.visit_unary(operator, right),
+    }
+}
+
+pub fn walk_binary<V: Visitor>(visitor: &mut V, left: &Expr, operator: &Token, right: &Expr) {
+    visitor.visit_expr(left);
+    visitor.visit_token(operator);
+    visitor.visit_expr(right);
+}
+
+pub fn walk_conditional<V: Visitor>(visitor: &mut V, condition: &Expr, then_branch: &Expr, else_branch: &Expr) {
+    visitor.visit_expr(condition);
+    visitor.visit_expr(then_branch);
+    visitor.visit_expr(else_branch);
+}
+
+pub fn walk_error<V: Visitor>(visitor: &mut V, expression: Option<&Expr>, tokens: &[Token]) {
+    if let Some(expression) = expression {
+        visitor.visit_expr(expression);
+    }
+    for token in tokens {
+        visitor.visit_token(token);
+    }
+}
+
+pub fn walk_grouping<V: Visitor>(visitor: &mut V, expression: &Expr) {
+    visitor.visit_expr(expression);
+}
+
+pub fn walk_index<V: Visitor>(visitor: &mut V, object: &Expr, index: &Expr) {
+    visitor.visit_expr(object);
+    visitor.visit_expr(index);
+}
+
+pub fn walk_index_assign<V: Visitor>(visitor: &mut V, object: &Expr, index: &Expr, operator: &Token, value: &Expr) {
+    visitor.visit_expr(object);
+    visitor.visit_expr(index);
+    visitor.visit_token(operator);
+    visitor.visit_expr(value);
+}
+
+pub fn walk_list<V: Visitor>(visitor: &mut V, elements: &[Expr]) {
+    for element in elements {
+        visitor.visit_expr(element);
+    }
+}
+
+pub fn walk_map<V: Visitor>(visitor: &mut V, entries: &[(Expr, Expr)]) {
+    for (key, value) in entries {
+        visitor.visit_expr(key);
+        visitor.visit_expr(value);
+    }
+}
+
+pub fn walk_postfix<V: Visitor>(visitor: &mut V, left: &Expr, operator: &Token) {
+    visitor.visit_expr(left);
+    visitor.visit_token(operator);
+}
+
+pub fn walk_unary<V: Visitor>(visitor: &mut V, operator: &Token, right: &Expr) {
+    visitor.visit_token(operator);
+    visitor.visit_expr(right);
+}
+
+// Same as Visitor, but for passes that rewrite the tree in place. To replace a node altogether
+// (e.g. folding a Binary into a Literal), override visit_expr_mut(), walk the children first and
+// then assign to the node.
+pub trait VisitorMut: Sized {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_binary_mut(&mut self, left: &mut Expr, operator: &mut Token, right: &mut Expr) {
+        walk_binary_mut(self, left, operator, right)
+    }
+
+    fn visit_conditional_mut(&mut self, condition: &mut Expr, then_branch: &mut Expr, else_branch: &mut Expr) {
+        walk_conditional_mut(self, condition, then_branch, else_branch)
+    }
+
+    fn visit_error_mut(&mut self, expression: Option<&mut Expr>, tokens: &mut [Token]) {
+        walk_error_mut(self, expression, tokens)
+    }
+
+    fn visit_grouping_mut(&mut self, expression: &mut Expr) {
+        walk_grouping_mut(self, expression)
+    }
+
+    fn visit_index_mut(&mut self, object: &mut Expr, index: &mut Expr) {
+        walk_index_mut(self, object, index)
+    }
+
+    fn visit_index_assign_mut(&mut self, object: &mut Expr, index: &mut Expr, operator: &mut Token, value: &mut Expr) {
+        walk_index_assign_mut(self, object, index, operator, value)
+    }
+
+    fn visit_list_mut(&mut self, elements: &mut [Expr]) {
+        walk_list_mut(self, elements)
+    }
+
+    fn visit_literal_mut(&mut self, _value: &mut Literal) {}
+
+    fn visit_map_mut(&mut self, entries: &mut [(Expr, Expr)]) {
+        walk_map_mut(self, entries)
+    }
+
+    fn visit_missing_mut(&mut self) {}
+
+    fn visit_postfix_mut(&mut self, left: &mut Expr, operator: &mut Token) {
+        walk_postfix_mut(self, left, operator)
+    }
+
+    fn visit_unary_mut(&mut self, operator: &mut Token, right: &mut Expr) {
+        walk_unary_mut(self, operator, right)
+    }
+
+    fn visit_token_mut(&mut self, _token: &mut Token) {}
+}
+
+pub fn walk_expr_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Expr) {
+    match &mut expr.kind {
+        ExprKind::Binary { left, operator, right } => visitor.visit_binary_mut(left, operator, right),
+        ExprKind::Conditional { condition, then_branch, else_branch } =>
+            visitor.visit_conditional_mut(condition, then_branch, else_branch),
+        ExprKind::Error { expression, tokens } => visitor.visit_error_mut(expression.as_deref_mut(), tokens),
+        ExprKind::Grouping { expression } => visitor.visit_grouping_mut(expression),
+        ExprKind::Index { object, index } => visitor.visit_index_mut(object, index),
+        ExprKind::IndexAssign { object, index, operator, value, .. } =>
+            visitor.visit_index_assign_mut(object, index, operator, value),
+        ExprKind::List { elements } => visitor.visit_list_mut(elements),
+        ExprKind::Literal { value } => visitor.visit_literal_mut(value),
+        ExprKind::Map { entries } => visitor.visit_map_mut(entries),
+        ExprKind::Missing => visitor.visit_missing_mut(),
+        ExprKind::Postfix { left, operator } => visitor.visit_postfix_mut(left, operator),
+        ExprKind::Unary { operator, right } => visitor.visit_unary_mut(operator, right),
+    }
+}
+
+pub fn walk_binary_mut<V: VisitorMut>(visitor: &mut V, left: &mut Expr, operator: &mut Token, right: &mut Expr) {
+    visitor.visit_expr_mut(left);
+    visitor.visit_token_mut(operator);
+    visitor.visit_expr_mut(right);
+}
+
+pub fn walk_conditional_mut<V: VisitorMut>(
+    visitor: &mut V,
+    condition: &mut Expr,
+    then_branch: &mut Expr,
+    else_branch: &mut Expr,
+) {
+    visitor.visit_expr_mut(condition);
+    visitor.visit_expr_mut(then_branch);
+    visitor.visit_expr_mut(else_branch);
+}
+
+pub fn walk_error_mut<V: VisitorMut>(visitor: &mut V, expression: Option<&mut Expr>, tokens: &mut [Token]) {
+    if let Some(expression) = expression {
+        visitor.visit_expr_mut(expression);
+    }
+    for token in tokens {
+        visitor.visit_token_mut(token);
+    }
+}
+
+pub fn walk_grouping_mut<V: VisitorMut>(visitor: &mut V, expression: &mut Expr) {
+    visitor.visit_expr_mut(expression);
+}
+
+pub fn walk_index_mut<V: VisitorMut>(visitor: &mut V, object: &mut Expr, index: &mut Expr) {
+    visitor.visit_expr_mut(object);
+    visitor.visit_expr_mut(index);
+}
+
+pub fn walk_index_assign_mut<V: VisitorMut>(
+    visitor: &mut V,
+    object: &mut Expr,
+    index: &mut Expr,
+    operator: &mut Token,
+    value: &mut Expr,
+) {
+    visitor.visit_expr_mut(object);
+    visitor.visit_expr_mut(index);
+    visitor.visit_token_mut(operator);
+    visitor.visit_expr_mut(value);
+}
+
+pub fn walk_list_mut<V: VisitorMut>(visitor: &mut V, elements: &mut [Expr]) {
+    for element in elements {
+        visitor.visit_expr_mut(element);
+    }
+}
+
+pub fn walk_map_mut<V: VisitorMut>(visitor: &mut V, entries: &mut [(Expr, Expr)]) {
+    for (key, value) in entries {
+        visitor.visit_expr_mut(key);
+        visitor.visit_expr_mut(value);
+    }
+}
+
+pub fn walk_postfix_mut<V: VisitorMut>(visitor: &mut V, left: &mut Expr, operator: &mut Token) {
+    visitor.visit_expr_mut(left);
+    visitor.visit_token_mut(operator);
+}
+
+pub fn walk_unary_mut<V: VisitorMut>(visitor: &mut V, operator: &mut Token, right: &mut Expr) {
+    visitor.visit_token_mut(operator);
+    visitor.visit_expr_mut(right);
+}
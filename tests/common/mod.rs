@@ -0,0 +1,26 @@
+// Helpers shared by the integration tests. Each test file is its own crate and uses only some of
+// them.
+#![allow(dead_code)]
+
+use yarli::Lox;
+use yarli::lexer::{Scanner, Span, Token, TokenType};
+use yarli::parser::{Expr, ExprKind, NodeId, Parser};
+
+pub fn scan(source: &str) -> Vec<Token> {
+    let mut lox = Lox { had_error: false };
+    Scanner::new(String::from(source), &mut lox).scan_tokens()
+}
+
+// Panics if `source` doesn't parse.
+pub fn parse(source: &str) -> Expr {
+    Parser::new(scan(source)).parse().unwrap()
+}
+
+// For building trees by hand. Spans don't matter to the printers.
+pub fn expr(id: u32, kind: ExprKind) -> Expr {
+    Expr { id: NodeId(id), span: Span::default(), kind }
+}
+
+pub fn token(token_type: TokenType, lexeme: &str) -> Token {
+    Token { token_type, lexeme: String::from(lexeme), literal: None, line: 1, span: Span::default() }
+}
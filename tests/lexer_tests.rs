@@ -1,5 +1,6 @@
 use std::path::Path;
 use yarli::Lox;
+use yarli::lexer::{Scanner, Token, TokenType};
 
 #[test]
 fn lexer_smoke_test() {
@@ -10,3 +11,110 @@ fn lexer_smoke_test() {
     let test2_path = testdata_dir.join("lexer_input_2.data");
     lox.run_file(test2_path.into_os_string().into_string().unwrap());
 }
+
+#[test]
+fn trivia_covers_the_rest_of_the_source_test() {
+    let source = "1 +\t// one\n  /* two */ 2 @";
+    let mut lox = Lox { had_error: false };
+    let (tokens, trivia) = Scanner::new(String::from(source), &mut lox).scan_tokens_with_trivia();
+    let mut all: Vec<Token> = tokens.into_iter().chain(trivia).collect();
+    all.sort_by_key(|token| token.span.start);
+    let text: String = all.iter().map(|token| token.lexeme.as_str()).collect();
+    assert_eq!(text, source);
+    let types: Vec<TokenType> = all.iter().map(|token| token.token_type).collect();
+    assert_eq!(types, vec![
+        TokenType::NUMBER, TokenType::WHITESPACE, TokenType::PLUS, TokenType::WHITESPACE, TokenType::COMMENT,
+        TokenType::WHITESPACE, TokenType::COMMENT, TokenType::WHITESPACE, TokenType::NUMBER, TokenType::WHITESPACE,
+        TokenType::UNKNOWN, TokenType::EOF,
+    ]);
+    // The whitespace after the line comment counts the newline
+    assert_eq!(all[5].line, 2);
+}
+
+#[test]
+fn ranges_and_decimal_points_test() {
+    let mut lox = Lox { had_error: false };
+    let tokens = Scanner::new(String::from("1..2 1..=2.5 1.5 a.b"), &mut lox).scan_tokens();
+    let lexemes: Vec<(TokenType, &str)> = tokens.iter().map(|token| (token.token_type, token.lexeme.as_str())).collect();
+    assert_eq!(lexemes, vec![
+        (TokenType::NUMBER, "1"), (TokenType::DOT_DOT, ".."), (TokenType::NUMBER, "2"),
+        (TokenType::NUMBER, "1"), (TokenType::DOT_DOT_EQUAL, "..="), (TokenType::NUMBER, "2.5"),
+        (TokenType::NUMBER, "1.5"),
+        (TokenType::IDENTIFIER, "a"), (TokenType::DOT, "."), (TokenType::IDENTIFIER, "b"),
+        (TokenType::EOF, ""),
+    ]);
+}
+
+#[test]
+fn arithmetic_operators_test() {
+    let mut lox = Lox { had_error: false };
+    let tokens = Scanner::new(String::from("2**3*4 % 5 ~/ 6 // comment"), &mut lox).scan_tokens();
+    let types: Vec<TokenType> = tokens.iter().map(|token| token.token_type).collect();
+    assert_eq!(types, vec![
+        TokenType::NUMBER, TokenType::STAR_STAR, TokenType::NUMBER, TokenType::STAR, TokenType::NUMBER,
+        TokenType::PERCENT, TokenType::NUMBER, TokenType::TILDE_SLASH, TokenType::NUMBER, TokenType::EOF,
+    ]);
+    assert!(!lox.had_error);
+}
+
+#[test]
+fn bitwise_operators_test() {
+    let mut lox = Lox { had_error: false };
+    let tokens = Scanner::new(String::from("~a & b | c ^ d << 1 >> 2 <= >= ~/"), &mut lox).scan_tokens();
+    let types: Vec<TokenType> = tokens.iter().map(|token| token.token_type).collect();
+    assert_eq!(types, vec![
+        TokenType::TILDE, TokenType::IDENTIFIER, TokenType::AMPERSAND, TokenType::IDENTIFIER, TokenType::PIPE,
+        TokenType::IDENTIFIER, TokenType::CARET, TokenType::IDENTIFIER, TokenType::LESS_LESS, TokenType::NUMBER,
+        TokenType::GREATER_GREATER, TokenType::NUMBER, TokenType::LESS_EQUAL, TokenType::GREATER_EQUAL,
+        TokenType::TILDE_SLASH, TokenType::EOF,
+    ]);
+    assert!(!lox.had_error);
+}
+
+#[test]
+fn assignment_operators_test() {
+    let mut lox = Lox { had_error: false };
+    let tokens = Scanner::new(String::from("++ -- += -= *= /= %= - - **= /* c */"), &mut lox).scan_tokens();
+    let types: Vec<TokenType> = tokens.iter().map(|token| token.token_type).collect();
+    assert_eq!(types, vec![
+        TokenType::PLUS_PLUS, TokenType::MINUS_MINUS, TokenType::PLUS_EQUAL, TokenType::MINUS_EQUAL,
+        TokenType::STAR_EQUAL, TokenType::SLASH_EQUAL, TokenType::PERCENT_EQUAL, TokenType::MINUS, TokenType::MINUS,
+        TokenType::STAR_STAR, TokenType::EQUAL, TokenType::EOF,
+    ]);
+    assert!(!lox.had_error);
+}
+
+// Spans are byte offsets, so they slice the source even past a multi-byte character.
+#[test]
+fn non_ascii_spans_test() {
+    let source = "\"héllo\" + 1 é";
+    let mut lox = Lox { had_error: false };
+    let (tokens, trivia) = Scanner::new(String::from(source), &mut lox).scan_tokens_with_trivia();
+    let lexemes: Vec<&str> = tokens.iter().map(|token| &source[token.span.start..token.span.end]).collect();
+    assert_eq!(lexemes, vec!["\"héllo\"", "+", "1", ""]);
+    assert_eq!(tokens[0].lexeme, "\"héllo\"");
+    assert_eq!(tokens[3].span.start, source.len());
+    let unknown: Vec<&str> =
+        trivia.iter().filter(|token| token.token_type == TokenType::UNKNOWN).map(|token| token.lexeme.as_str()).collect();
+    assert_eq!(unknown, vec!["é"]);
+    assert!(lox.had_error);
+}
+
+#[test]
+fn non_ascii_comment_test() {
+    let source = "// é\n1 /* ü */";
+    let mut lox = Lox { had_error: false };
+    let (tokens, comments) = Scanner::new(String::from(source), &mut lox).scan_tokens_with_comments();
+    let comments: Vec<&str> = comments.iter().map(|comment| comment.lexeme.as_str()).collect();
+    assert_eq!(comments, vec!["// é", "/* ü */"]);
+    assert_eq!(&source[tokens[0].span.start..tokens[0].span.end], "1");
+    assert!(!lox.had_error);
+}
+
+#[test]
+fn scan_errors_without_runner_test() {
+    let (_, trivia, errors) = Scanner::without_runner(String::from("1 @\n\"a")).scan_tokens_with_errors();
+    let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+    assert_eq!(errors, vec!["[line 1] Error: Unexpected character '@'", "[line 2] Error: Unterminated string."]);
+    assert_eq!(trivia.iter().filter(|token| token.token_type == TokenType::UNKNOWN).count(), 2);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::scan;
+use yarli::arena::{ArenaExprKind, ArenaOperator, ExprArena};
+use yarli::json::expr_to_json;
+use yarli::lexer::{LiteralValue, Span, TokenType};
+use yarli::parser::{ExprKind, Parser};
+
+#[test]
+fn arena_matches_boxed_tree_test() {
+    for source in ["1", "-(1 + 2) * 3 == \"x\"", "1 ? 2, 3 : 4 ? 5 : 6, 7", "!(!true) <= nil", "[[1], [], 2][0][0] = [3]", "[1][0] += [2][0]++", "{1: {}, \"b\": [2]}[1]"] {
+        let boxed = Parser::new(scan(source)).parse().unwrap();
+        let arena = ExprArena::parse(scan(source)).ok().unwrap();
+        // The JSON has every id, span, token and value in the tree
+        assert_eq!(expr_to_json(&arena.to_expr()), expr_to_json(&boxed), "{source}");
+    }
+}
+
+#[test]
+fn children_come_before_parents_test() {
+    let arena = ExprArena::parse(scan("1 + -(2 * 3) ? 4 : 5")).ok().unwrap();
+    assert_eq!(arena.len(), 10);
+    assert_eq!(arena.root().0 as usize, arena.len() - 1);
+    for (id, node) in arena.iter() {
+        let children = match node.kind {
+            ArenaExprKind::Binary { left, right, .. } => vec![left, right],
+            ArenaExprKind::Conditional { condition, then_branch, else_branch } => vec![condition, then_branch, else_branch],
+            ArenaExprKind::Error { expression, .. } => expression.into_iter().collect(),
+            ArenaExprKind::Grouping { expression } => vec![expression],
+            ArenaExprKind::Index { object, index } => vec![object, index],
+            ArenaExprKind::IndexAssign { object, index, value, .. } => vec![object, index, value],
+            ArenaExprKind::List { elements } => arena.elements(elements).to_vec(),
+            ArenaExprKind::Map { entries } => arena.elements(entries).to_vec(),
+            ArenaExprKind::Literal { .. } | ArenaExprKind::Missing => vec![],
+            ArenaExprKind::Postfix { left, .. } => vec![left],
+            ArenaExprKind::Unary { right, .. } => vec![right],
+        };
+        assert!(children.iter().all(|child| *child < id));
+    }
+}
+
+#[test]
+fn operators_and_literals_test() {
+    let arena = ExprArena::parse(scan("1 + 2")).ok().unwrap();
+    let ArenaExprKind::Binary { left, operator, .. } = arena[arena.root()].kind else { panic!("expected a binary expression") };
+    assert_eq!(arena.operator(operator), ArenaOperator { token_type: TokenType::PLUS, line: 1, span: Span::new(2, 3) });
+    let ArenaExprKind::Literal { value } = arena[left].kind else { panic!("expected a literal") };
+    assert_eq!(arena.literal(value), &Some(LiteralValue::Number(1.0)));
+}
+
+#[test]
+fn index_assign_has_no_index_node_test() {
+    let arena = ExprArena::parse(scan("[1][0] = 2")).ok().unwrap();
+    assert_eq!(arena.len(), 5);
+    assert!(arena.iter().all(|(_, node)| !matches!(node.kind, ArenaExprKind::Index { .. })));
+    let expr = arena.to_expr();
+    assert_eq!(expr.id, arena.root());
+    let ExprKind::IndexAssign { closing, .. } = expr.kind else { panic!("expected an index assignment") };
+    assert_eq!(closing, Span::new(5, 6));
+}
+
+#[test]
+fn arena_parse_errors_test() {
+    let errors = ExprArena::parse(scan("* 1 + (2")).err().unwrap();
+    let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+    assert_eq!(errors, vec![
+        "[line 1] Error at '*': Missing left-hand operand for '*'",
+        "[line 1] Error at end: Expect ')' after expression.",
+    ]);
+}
+
+#[test]
+fn arena_parse_tolerant_matches_boxed_tree_test() {
+    for source in ["1 +", "(1 2", "foo ? : 3", "1 ) 2", "{1:2}[0] = 1 = [2]"] {
+        let parser = Parser::new(scan(source));
+        let boxed = parser.parse_tolerant();
+        let (arena, errors) = ExprArena::parse_tolerant(scan(source));
+        assert_eq!(expr_to_json(&arena.to_expr()), expr_to_json(&boxed), "{source}");
+        assert_eq!(errors.len(), parser.errors().len(), "{source}");
+    }
+}
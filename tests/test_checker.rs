@@ -0,0 +1,68 @@
+mod common;
+
+use common::parse;
+use yarli::checker::{check, Type};
+
+fn check_source(source: &str) -> (Type, Vec<String>) {
+    let (expr_type, errors) = check(source, &parse(source));
+    (expr_type, errors.iter().map(|e| e.to_string()).collect())
+}
+
+fn type_of(source: &str) -> Type {
+    let (expr_type, errors) = check_source(source);
+    assert_eq!(errors, Vec::<String>::new(), "{source}");
+    expr_type
+}
+
+fn errors(source: &str) -> Vec<String> {
+    check_source(source).1
+}
+
+#[test]
+fn inference_test() {
+    assert_eq!(type_of("1 + 2 * 3"), Type::Number);
+    assert_eq!(type_of("\"a\" + \"b\""), Type::String);
+    assert_eq!(type_of("1 < 2 == !nil"), Type::Bool);
+    assert_eq!(type_of("nil"), Type::Nil);
+    assert_eq!(type_of("(1, \"a\")"), Type::String);
+    assert_eq!(type_of("true ? 1 : 2"), Type::Number);
+    assert_eq!(type_of("true ? 1 : \"a\""), Type::Any);
+    assert_eq!(type_of("[1, \"a\"]"), Type::List);
+    assert_eq!(type_of("{1: 2}"), Type::Map);
+    assert_eq!(type_of("0 ..= 9"), Type::Range);
+    assert_eq!(type_of("-2 ** 3 % 4 ~/ 5 & ~6 << 1"), Type::Number);
+}
+
+// Whatever isn't known statically goes with everything.
+#[test]
+fn gradual_typing_test() {
+    assert_eq!(type_of("[1][0]"), Type::Any);
+    assert_eq!(type_of("[1][0] + \"a\""), Type::String);
+    assert_eq!(type_of("{1: 2}[\"a\"] - 1"), Type::Number);
+    assert_eq!(type_of("[1][0] + [2][0]"), Type::Any);
+    assert_eq!(type_of("[1][0] = true"), Type::Bool);
+    assert_eq!(type_of("[1][0] += 1"), Type::Number);
+    assert_eq!(type_of("-[1][0]++"), Type::Number);
+}
+
+#[test]
+fn type_errors_test() {
+    assert_eq!(errors("\"a\" - 1"), vec!["[line 1] Error at '-': Operands must be numbers."]);
+    assert_eq!(errors("1 + \"a\""), vec!["[line 1] Error at '+': Operands must be two numbers or two strings."]);
+    assert_eq!(errors("-\"a\""), vec!["[line 1] Error at '-': Operand must be a number."]);
+    assert_eq!(errors("true < 1"), vec!["[line 1] Error at '<': Operands must be numbers."]);
+    assert_eq!(errors("1 .. \"a\""), vec!["[line 1] Error at '..': Operands must be numbers."]);
+    assert_eq!(errors("[1][0] -= \"a\""), vec!["[line 1] Error at '-=': Operands must be numbers."]);
+    assert_eq!(errors("1\n+ (2)[0]"), vec!["[line 2] Error at '(2)': Only lists and maps can be indexed."]);
+    assert_eq!(errors("[1][\"a\"]"), vec!["[line 1] Error at '\"a\"': List index must be a number."]);
+    assert_eq!(errors("1 + (2 +\n3)[0]"), vec!["[line 1] Error at '(2 +': Only lists and maps can be indexed."]);
+    // Each error is reported once, and the result type keeps the rest in check.
+    assert_eq!(
+        errors("(nil * 2) + \"a\", true - false"),
+        vec![
+            "[line 1] Error at '*': Operands must be numbers.",
+            "[line 1] Error at '+': Operands must be two numbers or two strings.",
+            "[line 1] Error at '-': Operands must be numbers.",
+        ]
+    );
+}
@@ -0,0 +1,111 @@
+use yarli::Lox;
+use yarli::cst::{parse, AstExpr, NodeKind, SourceFile, SyntaxElement, SyntaxNode};
+use yarli::lexer::{LiteralValue, Scanner, Span, TokenType};
+
+fn parse_source(source: &str) -> SyntaxNode {
+    let mut lox = Lox { had_error: false };
+    let (tokens, trivia) = Scanner::new(String::from(source), &mut lox).scan_tokens_with_trivia();
+    parse(tokens, trivia).0
+}
+
+// The first node of that kind, in preorder
+fn find(node: &SyntaxNode, kind: NodeKind) -> Option<SyntaxNode> {
+    if node.kind() == kind {
+        return Some(node.clone());
+    }
+    node.children().iter().find_map(|child| find(child, kind))
+}
+
+#[test]
+fn round_trip_test() {
+    for source in [
+        "",
+        "  1 + 2 ",
+        "// leading\n1 /* one */ + (\n\t2 * -3 // three\n) // trailing",
+        "1 ? (2, 3) : \"four\" == nil",
+        "1 + foo bar * ) 2 : (",
+        "1 + @ # 2",
+        "1 + \"unterminated",
+        "1 /* unfinished",
+        "[1, /* two */ 2][0] = [ ]",
+        "\"é\" + é // ü",
+    ] {
+        assert_eq!(parse_source(source).text(), source);
+    }
+}
+
+#[test]
+fn every_byte_in_one_token_test() {
+    let source = "// é\n-(1 +  \"ü\") ? 3 : é // d\n";
+    let root = parse_source(source);
+    let mut end = 0;
+    for token in root.tokens() {
+        assert_eq!(token.span().start, end);
+        assert_eq!(&source[token.span().start..token.span().end], token.text());
+        end = token.span().end;
+    }
+    assert_eq!(end, source.len());
+}
+
+#[test]
+fn trivia_goes_to_innermost_node_test() {
+    let root = parse_source("/* a */ (1 /* b */) // c");
+    let root_tokens: Vec<TokenType> = root.children_with_tokens().iter().filter_map(|element| match element {
+        SyntaxElement::Token(token) => Some(token.kind()),
+        SyntaxElement::Node(_) => None,
+    }).collect();
+    assert_eq!(root_tokens, vec![
+        TokenType::COMMENT, TokenType::WHITESPACE, TokenType::WHITESPACE, TokenType::COMMENT, TokenType::EOF,
+    ]);
+    let grouping = find(&root, NodeKind::Grouping).unwrap();
+    assert_eq!(grouping.text(), "(1 /* b */)");
+    assert_eq!(grouping.span(), Span::new(8, 19));
+}
+
+#[test]
+fn invalid_assignment_target_test() {
+    let root = parse_source("1 = [1, 2, 3]");
+    let error = find(&root, NodeKind::Error).unwrap();
+    assert_eq!(error.text(), "1 = [1, 2, 3]");
+    assert_eq!(root.children().len(), 1);
+
+    let root = parse_source("{1:2}[0] = 1 = 2");
+    let assignment = find(&root, NodeKind::IndexAssign).unwrap();
+    assert_eq!(assignment.text(), "{1:2}[0] = 1 = 2");
+    assert_eq!(find(&assignment, NodeKind::Error).unwrap().text(), "1 = 2");
+}
+
+#[test]
+fn typed_view_test() {
+    let root = SourceFile::cast(parse_source("1 + -\"two\" * 3")).unwrap();
+    let Some(AstExpr::Binary(sum)) = root.expression() else { panic!("expected a binary expression") };
+    assert_eq!(sum.operator().unwrap().text(), "+");
+    let Some(AstExpr::Literal(one)) = sum.left() else { panic!("expected a literal") };
+    assert_eq!(one.value(), Some(LiteralValue::Number(1.0)));
+    let Some(AstExpr::Binary(product)) = sum.right() else { panic!("expected a binary expression") };
+    let Some(AstExpr::Unary(negation)) = product.left() else { panic!("expected a unary expression") };
+    let Some(AstExpr::Literal(two)) = negation.operand() else { panic!("expected a literal") };
+    assert_eq!(two.value(), Some(LiteralValue::String(String::from("two"))));
+    assert_eq!(product.syntax().text(), "-\"two\" * 3");
+}
+
+#[test]
+fn typed_view_of_broken_code_test() {
+    let root = SourceFile::cast(parse_source("1 +")).unwrap();
+    let Some(AstExpr::Binary(sum)) = root.expression() else { panic!("expected a binary expression") };
+    assert!(matches!(sum.right(), Some(AstExpr::Missing(_))));
+}
+
+#[test]
+fn replace_keeps_the_rest_test() {
+    let root = parse_source("// sum\n1 /* one */ + (2   *   3) // done\n");
+    let grouping = find(&root, NodeKind::Grouping).unwrap();
+    let product = find(&grouping, NodeKind::Binary).unwrap();
+    assert_eq!(product.text(), "2   *   3");
+
+    let replacement = find(&parse_source("6"), NodeKind::Literal).unwrap();
+    let new_root = product.replace_with(replacement.green().clone());
+    assert_eq!(new_root.to_string(), "// sum\n1 /* one */ + (6) // done\n");
+    // The original tree is untouched
+    assert_eq!(root.text(), "// sum\n1 /* one */ + (2   *   3) // done\n");
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use common::{expr, token};
+use yarli::lexer::{LiteralValue, TokenType};
+use yarli::parser::{ExprKind, DotPrinter};
+
+#[test]
+fn dot_printer_test() {
+    let expected = "digraph ast {
+    ordering=out;
+    node [shape=box];
+    n3 [label=\"==\"];
+    n3 -> n1;
+    n1 [label=\"-\"];
+    n1 -> n0;
+    n0 [label=\"1\"];
+    n3 -> n2;
+    n2 [label=\"\\\"a \\\\ b\\\"\"];
+}";
+    let equal_token = token(TokenType::EQUAL_EQUAL, "==");
+    let minus_token = token(TokenType::MINUS, "-");
+    let expression = expr(3, ExprKind::Binary {
+        left: Box::new(expr(1, ExprKind::Unary {
+            operator: minus_token,
+            right: Box::new(expr(0, ExprKind::Literal { value: Some(LiteralValue::Number(1.0)) }))
+        })),
+        operator: equal_token,
+        right: Box::new(expr(2, ExprKind::Literal { value: Some(LiteralValue::String(String::from("a \\ b"))) }))
+    });
+
+    let result = DotPrinter.print(&expression);
+    assert_eq!(&result, expected);
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use common::parse;
+use yarli::{FormatStatus, Lox};
+use yarli::lexer::Scanner;
+use yarli::parser::AstPrinter;
+
+fn format(source: &str, width: usize) -> String {
+    let mut lox = Lox { had_error: false };
+    lox.format(String::from(source), width).unwrap()
+}
+
+fn ast(source: &str) -> String {
+    AstPrinter.print(&parse(source))
+}
+
+fn comments(source: &str) -> Vec<String> {
+    let mut lox = Lox { had_error: false };
+    let (_, comments) = Scanner::new(String::from(source), &mut lox).scan_tokens_with_comments();
+    comments.into_iter().map(|comment| comment.lexeme).collect()
+}
+
+const SOURCES: &[&str] = &[
+    "1+2*3",
+    "-(1+2)  /  !true",
+    "(((1)))",
+    "1 ? 2 : 3 ? 4 : 5",
+    "1 ? (2, 3) : 4 , 5,6",
+    "\"some string\" == \"other string\" != nil",
+    "1.50 >= 2 == 3 < 4.25",
+    "// leading comment\n1 + /* inline */ 2 // trailing comment",
+    "(1 + 2 // inside the group\n)",
+    "1 /* before the operator */ - 2",
+    "1 +\n// on its own line\n2\n/* after everything */",
+    "123456789 + 123456789 * 123456789 - (123456789 / 123456789 + 123456789) * 123456789 ? 123456789 : 987654321",
+    "(1111111111, 2222222222, 3333333333, 4444444444, 5555555555, 6666666666, 7777777777, 8888888888)",
+    "[] == [1,2,[3]][0][1]",
+    "[1111111111, 2222222222, 3333333333, 4444444444, 5555555555][1 + 1] = [1, 2 /* two */]",
+    "{\"one\":1, \"two\" : {}, true ? 1 : 2: [3]}",
+    "[1..(2 + 3), -1 ..= 1]",
+    "-2**3**2 % 4~/5",
+    "~1&255|2<<8^3>>2",
+    "[1][0]+=- -1, ++[2][0], [3][0]--",
+    "~ /* c */ 1",
+    "~ // c\n1",
+    "[1,2,3][0] /* c */ = 4",
+    "[1,2,3][0 /* c */] = 4",
+    "// é\n\"ünïcode\" + /* ü */ 1",
+];
+
+#[test]
+fn spacing_test() {
+    assert_eq!(format("1+2*(3-4)", 80), "1 + 2 * (3 - 4)\n");
+    assert_eq!(format("  - 1 ,2?3:4", 80), "-1, 2 ? 3 : 4\n");
+    assert_eq!(format("1.50+\"a  b\"", 80), "1.50 + \"a  b\"\n");
+    assert_eq!(format("[ 1,2 ] [ 0 ]=3", 80), "[1, 2][0] = 3\n");
+    assert_eq!(format("-2**3%4~/5", 80), "-2 ** 3 % 4 ~/ 5\n");
+    assert_eq!(format("~1&2|3<<4", 80), "~1 & 2 | 3 << 4\n");
+    assert_eq!(format("[1][0]*=- -1- --[2][0]", 80), "[1][0] *= - -1 - --[2][0]\n");
+    assert_eq!(format("~ /* c */ 1", 80), "~ /* c */ 1\n");
+    assert_eq!(format("- /* c */ 1", 80), "-/* c */ 1\n");
+    assert_eq!(format("- \"a\"", 80), "-\"a\"\n");
+    assert_eq!(format("- -1", 80), "- -1\n");
+    assert_eq!(format("! !true", 80), "!!true\n");
+    assert_eq!(format("0 .. 1+1 == 1..= 2", 80), "0..1 + 1 == 1..=2\n");
+    assert_eq!(format("{ \"a\":1,\"b\" :2 }", 80), "{\"a\": 1, \"b\": 2}\n");
+}
+
+#[test]
+fn line_wrapping_test() {
+    assert_eq!(format("1000 + 2000 + 3000 + 4000", 20), "1000\n    + 2000\n    + 3000\n    + 4000\n");
+    assert_eq!(format("1000 * 2000 + 3000 * 4000", 20), "1000 * 2000\n    + 3000 * 4000\n");
+    assert_eq!(format("true ? 1000000 : 2000000", 20), "true\n    ? 1000000\n    : 2000000\n");
+    assert_eq!(format("(1000 + 2000) * 3000", 15), "(1000 + 2000)\n    * 3000\n");
+    assert_eq!(format("(1000 + 2000)", 10), "(\n    1000\n        + 2000\n)\n");
+    assert_eq!(format("1000, 2000, 3000", 10), "1000,\n2000,\n3000\n");
+    assert_eq!(format("[1000, 2000, 3000]", 10), "[\n    1000,\n    2000,\n    3000\n]\n");
+}
+
+#[test]
+fn comments_are_preserved_test() {
+    for source in SOURCES {
+        for width in [10, 40, 80] {
+            assert_eq!(comments(&format(source, width)), comments(source), "{source}");
+        }
+    }
+    assert_eq!(
+        format("// leading comment\n1 + /* inline */ 2 // trailing comment", 80),
+        "// leading comment\n1 + /* inline */ 2 // trailing comment\n"
+    );
+    // Like for binary operators, a comment before '=' goes after it.
+    assert_eq!(format("[1,2,3][0] /* c */ = 4", 80), "[1, 2, 3][0] = /* c */ 4\n");
+    assert_eq!(format("[1,2,3][0 /* c */] = 4", 80), "[1, 2, 3][0 /* c */] = 4\n");
+}
+
+#[test]
+fn round_trip_test() {
+    for source in SOURCES {
+        for width in [10, 40, 80] {
+            let formatted = format(source, width);
+            assert_eq!(ast(&formatted), ast(source), "{formatted}");
+            assert_eq!(format(&formatted, width), formatted, "{formatted}");
+        }
+    }
+}
+
+#[test]
+fn unparsable_source_test() {
+    let mut lox = Lox { had_error: false };
+    assert!(lox.format(String::from("1 +"), 80).is_none());
+    assert!(lox.had_error);
+
+    let mut lox = Lox { had_error: false };
+    assert!(lox.format(String::from("1 2"), 80).is_none());
+    assert!(lox.had_error);
+}
+
+#[test]
+fn format_file_status_test() {
+    let dir = std::env::temp_dir().join(format!("yarli-format-file-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = |name: &str, source: &str| {
+        let path = dir.join(name);
+        std::fs::write(&path, source).unwrap();
+        path.into_os_string().into_string().unwrap()
+    };
+    let mut lox = Lox { had_error: false };
+    assert_eq!(lox.format_file(file("unchanged.lox", "1 + 2\n"), true, 80), FormatStatus::Unchanged);
+    assert_eq!(lox.format_file(file("invalid.lox", "1 +"), true, 80), FormatStatus::Invalid);
+    lox.had_error = false;
+    let changed = file("changed.lox", "1+2");
+    assert_eq!(lox.format_file(changed.clone(), false, 80), FormatStatus::Changed);
+    assert_eq!(std::fs::read_to_string(&changed).unwrap(), "1 + 2\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
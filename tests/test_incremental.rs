@@ -0,0 +1,105 @@
+use std::rc::Rc;
+use yarli::cst::{NodeKind, SyntaxNode};
+use yarli::incremental::{Parse, TextEdit};
+use yarli::lexer::Span;
+
+fn edit(start: usize, end: usize, text: &str) -> TextEdit {
+    TextEdit { span: Span::new(start, end), text: String::from(text) }
+}
+
+// Checks that the incremental parse agrees with a full parse of the edited text.
+fn check(previous: &Parse, edit: &TextEdit) -> Parse {
+    let text = previous.text();
+    let expected = format!("{}{}{}", &text[..edit.span.start], edit.text, &text[edit.span.end..]);
+    let incremental = previous.edit(edit);
+    let full = Parse::new(&expected);
+    assert_eq!(incremental.text(), expected);
+    assert_eq!(incremental.root().green(), full.root().green(), "{text:?} with {edit:?}");
+    let errors = |parse: &Parse| parse.errors().iter().map(|error| error.to_string()).collect::<Vec<_>>();
+    assert_eq!(errors(&incremental), errors(&full), "{text:?} with {edit:?}");
+    incremental
+}
+
+fn groups(root: &SyntaxNode) -> Vec<SyntaxNode> {
+    let mut groups = Vec::new();
+    for child in root.children() {
+        if child.kind() == NodeKind::Grouping {
+            groups.push(child.clone());
+        }
+        groups.extend(self::groups(&child));
+    }
+    groups
+}
+
+#[test]
+fn token_edit_keeps_the_rest_test() {
+    let previous = Parse::new("(1 + 2) * (3 + 4)");
+    let parse = check(&previous, &edit(11, 11, "5"));
+    assert_eq!(parse.text(), "(1 + 2) * (53 + 4)");
+    assert!(Rc::ptr_eq(groups(previous.root())[0].green(), groups(parse.root())[0].green()));
+}
+
+#[test]
+fn group_edit_keeps_the_rest_test() {
+    let previous = Parse::new("(1 + 2) * (3 + 4) // comment");
+    let parse = check(&previous, &edit(13, 14, "== -"));
+    assert_eq!(parse.text(), "(1 + 2) * (3 == - 4) // comment");
+    assert!(Rc::ptr_eq(groups(previous.root())[0].green(), groups(parse.root())[0].green()));
+}
+
+#[test]
+fn edits_that_change_the_structure_test() {
+    let previous = Parse::new("(1 + 2) * 3");
+    // Removes the closing parenthesis, then puts it back elsewhere
+    let broken = check(&previous, &edit(6, 7, ""));
+    assert!(!broken.errors().is_empty());
+    let fixed = check(&broken, &edit(10, 10, ")"));
+    assert_eq!(fixed.text(), "(1 + 2 * 3)");
+    assert!(fixed.errors().is_empty());
+}
+
+#[test]
+fn scan_errors_test() {
+    let previous = Parse::new("(1 + 2) * 3");
+    let broken = check(&previous, &edit(1, 1, "\""));
+    let errors: Vec<String> = broken.errors().iter().map(|error| error.to_string()).collect();
+    assert_eq!(errors[0], "[line 1] Error: Unterminated string.");
+    let fixed = check(&broken, &edit(1, 2, ""));
+    assert!(fixed.errors().is_empty());
+}
+
+#[test]
+fn non_ascii_edits_test() {
+    let source = "(\"é\" + 1) /* ü */ * (2)";
+    let previous = Parse::new(source);
+    for (start, _) in source.char_indices() {
+        for text in ["", "ö", "1", ")", "\""] {
+            check(&previous, &edit(start, start, text));
+        }
+    }
+    let parse = check(&previous, &edit(2, 4, "ñø"));
+    assert_eq!(parse.text(), "(\"ñø\" + 1) /* ü */ * (2)");
+}
+
+#[test]
+fn incremental_and_full_parses_agree_test() {
+    let sources = [
+        "(1 + 2) * (3 - (4 / 5))",
+        "-(12.5 >= 3) ? (\"a\" , nil) : (!true)",
+        "(1 /* one */ + // two\n 2)",
+        "(1 + )",
+        "[1, (2 + 3)][0] = [(4)]",
+        "{\"a\": (1), (2): {}}",
+    ];
+    let replacements = ["", "1", "9.", " ", "\n", "(", ")", "[", "]", "{", "}", ",", "-", "=", "/", "*", "\"", "//", "1 ?", ": 2"];
+    for source in sources {
+        let previous = Parse::new(source);
+        for start in 0..=source.len() {
+            for end in start..=(start + 2).min(source.len()) {
+                for text in replacements {
+                    check(&previous, &edit(start, end, text));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use yarli::{Dump, Lox};
+
+fn dump(source: &str, dump: Dump) -> String {
+    let mut lox = Lox { had_error: false };
+    lox.dump(String::from(source), dump).unwrap()
+}
+
+#[test]
+fn tokens_json_test() {
+    assert_eq!(
+        dump("\"foo\" 1.5", Dump::TokensJson),
+        concat!(
+            "[",
+            r#"{"type":"STRING","lexeme":"\"foo\"","literal":"foo","line":1,"span":{"start":0,"end":5}},"#,
+            r#"{"type":"NUMBER","lexeme":"1.5","literal":1.5,"line":1,"span":{"start":6,"end":9}},"#,
+            r#"{"type":"EOF","lexeme":"","literal":null,"line":1,"span":{"start":9,"end":9}}"#,
+            "]",
+        )
+    );
+}
+
+#[test]
+fn strings_are_escaped_test() {
+    assert!(dump("\"a\\b\nc\"", Dump::TokensJson).contains(r#""literal":"a\\b\nc""#));
+}
+
+#[test]
+fn ast_json_test() {
+    assert_eq!(
+        dump("-1 + true", Dump::AstJson),
+        concat!(
+            r#"{"id":3,"span":{"start":0,"end":9},"kind":"Binary","#,
+            r#""left":{"id":1,"span":{"start":0,"end":2},"kind":"Unary","#,
+            r#""operator":{"type":"MINUS","lexeme":"-","literal":null,"line":1,"span":{"start":0,"end":1}},"#,
+            r#""right":{"id":0,"span":{"start":1,"end":2},"kind":"Literal","value":1}},"#,
+            r#""operator":{"type":"PLUS","lexeme":"+","literal":null,"line":1,"span":{"start":3,"end":4}},"#,
+            r#""right":{"id":2,"span":{"start":5,"end":9},"kind":"Literal","value":true}}"#,
+        )
+    );
+}
+
+#[test]
+fn list_json_test() {
+    assert_eq!(
+        dump("[nil][0]", Dump::AstJson),
+        concat!(
+            r#"{"id":3,"span":{"start":0,"end":8},"kind":"Index","#,
+            r#""object":{"id":1,"span":{"start":0,"end":5},"kind":"List","#,
+            r#""elements":[{"id":0,"span":{"start":1,"end":4},"kind":"Literal","value":null}]},"#,
+            r#""index":{"id":2,"span":{"start":6,"end":7},"kind":"Literal","value":0}}"#,
+        )
+    );
+}
+
+#[test]
+fn ast_json_needs_a_valid_expression_test() {
+    let mut lox = Lox { had_error: false };
+    assert!(lox.dump(String::from("1 +"), Dump::AstJson).is_none());
+    assert!(lox.had_error);
+}
@@ -0,0 +1,315 @@
+mod common;
+
+use common::{parse, scan};
+use yarli::lexer::Span;
+use yarli::parser::{AstPrinter, Expr, ExprKind, NodeId, Parser, RpnPrinter};
+use yarli::visitor::{walk_expr, Visitor};
+
+fn parse_errors(source: &str) -> Vec<String> {
+    let parser = Parser::new(scan(source));
+    assert!(parser.parse().is_none());
+    let errors = parser.errors().iter().map(|e| e.to_string()).collect();
+    errors
+}
+
+fn parse_to_ast(source: &str) -> String {
+    AstPrinter.print(&parse(source))
+}
+
+fn parse_tolerant(source: &str) -> (String, Vec<String>) {
+    let parser = Parser::new(scan(source));
+    let ast = AstPrinter.print(&parser.parse_tolerant());
+    let errors = parser.errors().iter().map(|e| e.to_string()).collect();
+    (ast, errors)
+}
+
+#[test]
+fn conditional_test() {
+    assert_eq!(parse_to_ast("1 == 2 ? 3 : 4"), "(?: (== 1 2) 3 4)");
+}
+
+#[test]
+fn conditional_is_right_associative_test() {
+    assert_eq!(parse_to_ast("1 ? 2 : 3 ? 4 : 5"), "(?: 1 2 (?: 3 4 5))");
+    assert_eq!(parse_to_ast("1 ? 2 ? 3 : 4 : 5"), "(?: 1 (?: 2 3 4) 5)");
+}
+
+#[test]
+fn comma_test() {
+    assert_eq!(parse_to_ast("1, 2 + 3, 4"), "(, (, 1 (+ 2 3)) 4)");
+    assert_eq!(parse_to_ast("1, 2 ? 3, 4 : 5"), "(, 1 (?: 2 (, 3 4) 5))");
+}
+
+#[test]
+fn rpn_conditional_test() {
+    let parser = Parser::new(scan("1 < 2 ? 3 : 4"));
+    assert_eq!(RpnPrinter.print(&parser.parse().unwrap()), "1 2 < 3 4 ?:");
+}
+
+#[test]
+fn missing_left_operand_test() {
+    assert_eq!(parse_errors("* 3"), vec!["[line 1] Error at '*': Missing left-hand operand for '*'"]);
+    assert_eq!(parse_errors("\n== 2"), vec!["[line 2] Error at '==': Missing left-hand operand for '=='"]);
+}
+
+#[test]
+fn missing_left_operand_keeps_parsing_test() {
+    assert_eq!(
+        parse_errors("< 1 + 2 == / (3"),
+        vec![
+            "[line 1] Error at '<': Missing left-hand operand for '<'",
+            "[line 1] Error at '/': Missing left-hand operand for '/'",
+            "[line 1] Error at end: Expect ')' after expression.",
+        ]
+    );
+}
+
+#[test]
+fn expect_expression_test() {
+    assert_eq!(parse_errors("1 +"), vec!["[line 1] Error at end: Expect expression."]);
+    assert_eq!(parse_errors("1 ? 2"), vec!["[line 1] Error at end: Expect ':' after then branch of conditional expression."]);
+}
+
+#[test]
+fn spans_test() {
+    let source = "1 + (2 * -3)";
+    let expr = Parser::new(scan(source)).parse().unwrap();
+    assert_eq!(expr.span, Span::new(0, 12));
+    let ExprKind::Binary { left, right, .. } = &expr.kind else { panic!("expected a binary expression") };
+    assert_eq!(&source[left.span.start..left.span.end], "1");
+    assert_eq!(&source[right.span.start..right.span.end], "(2 * -3)");
+}
+
+#[test]
+fn node_ids_test() {
+    struct IdCollector {
+        ids: Vec<NodeId>,
+    }
+
+    impl Visitor for IdCollector {
+        fn visit_expr(&mut self, expr: &Expr) {
+            self.ids.push(expr.id);
+            walk_expr(self, expr);
+        }
+    }
+
+    let source = "1 ? (2, 3) : -4 == 5";
+    let mut collector = IdCollector { ids: Vec::new() };
+    collector.visit_expr(&Parser::new(scan(source)).parse().unwrap());
+    let mut ids = collector.ids.clone();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 10);
+
+    // The same source always gets the same ids
+    let mut again = IdCollector { ids: Vec::new() };
+    again.visit_expr(&Parser::new(scan(source)).parse().unwrap());
+    assert_eq!(collector.ids, again.ids);
+}
+
+#[test]
+fn precedence_and_associativity_test() {
+    assert_eq!(parse_to_ast("1 - 2 - 3"), "(- (- 1 2) 3)");
+    assert_eq!(parse_to_ast("-1 * 2 + 3 / 4"), "(+ (* (- 1) 2) (/ 3 4))");
+    assert_eq!(parse_to_ast("1 < 2 == 3 >= 4"), "(== (< 1 2) (>= 3 4))");
+    assert_eq!(parse_to_ast("!!true != false"), "(!= (! (! true)) false)");
+    assert_eq!(parse_to_ast("1 + 2 ? 3 : 4, 5"), "(, (?: (+ 1 2) 3 4) 5)");
+}
+
+#[test]
+fn long_expression_test() {
+    let source = vec!["1"; 1_000].join(" + ");
+    let parser = Parser::new(scan(&source));
+    assert!(parser.parse().is_some());
+}
+
+#[test]
+fn tolerant_parse_of_valid_code_test() {
+    let source = "1 ? (2, 3) : -4 == 5";
+    assert_eq!(parse_tolerant(source), (parse_to_ast(source), vec![]));
+}
+
+#[test]
+fn tolerant_missing_operands_test() {
+    assert_eq!(
+        parse_tolerant("1 +"),
+        (String::from("(+ 1 <missing>)"), vec![String::from("[line 1] Error at end: Expect expression.")])
+    );
+    assert_eq!(
+        parse_tolerant("* 3"),
+        (String::from("(* <missing> 3)"), vec![String::from("[line 1] Error at '*': Missing left-hand operand for '*'")])
+    );
+}
+
+#[test]
+fn tolerant_missing_tokens_test() {
+    assert_eq!(
+        parse_tolerant("(1 + 2"),
+        (String::from("(group (+ 1 2))"), vec![String::from("[line 1] Error at end: Expect ')' after expression.")])
+    );
+    assert_eq!(
+        parse_tolerant("1 ? 2"),
+        (
+            String::from("(?: 1 2 <missing>)"),
+            vec![String::from("[line 1] Error at end: Expect ':' after then branch of conditional expression.")]
+        )
+    );
+}
+
+#[test]
+fn tolerant_unexpected_tokens_test() {
+    assert_eq!(
+        parse_tolerant("1 + foo bar * 2"),
+        (String::from("(+ 1 (* (error foo bar) 2))"), vec![String::from("[line 1] Error at 'foo': Expect expression.")])
+    );
+    assert_eq!(
+        parse_tolerant("1 2 3 + 4"),
+        (String::from("(+ (error 1 2 3) 4)"), vec![String::from("[line 1] Error at '2': Expect end of expression.")])
+    );
+    assert_eq!(
+        parse_tolerant("(1) ) 2"),
+        (String::from("(error (group 1) ) 2)"), vec![String::from("[line 1] Error at ')': Expect end of expression.")])
+    );
+}
+
+#[test]
+fn tolerant_tree_spans_test() {
+    let source = "1 + foo";
+    let parser = Parser::new(scan(source));
+    let expr = parser.parse_tolerant();
+    assert_eq!(expr.span, Span::new(0, 7));
+    let ExprKind::Binary { right, .. } = &expr.kind else { panic!("expected a binary expression") };
+    let ExprKind::Error { expression: None, tokens } = &right.kind else { panic!("expected an error node") };
+    assert_eq!(tokens[0].lexeme, "foo");
+    assert_eq!(right.span, Span::new(4, 7));
+
+    let expr = Parser::new(scan("1 +")).parse_tolerant();
+    let ExprKind::Binary { right, .. } = &expr.kind else { panic!("expected a binary expression") };
+    assert!(matches!(right.kind, ExprKind::Missing));
+    assert_eq!(right.span, Span::new(3, 3));
+}
+
+#[test]
+fn list_test() {
+    assert_eq!(parse_to_ast("[]"), "(list)");
+    assert_eq!(parse_to_ast("[1, 2 + 3, [true]]"), "(list 1 (+ 2 3) (list true))");
+    // The comma separates elements, but can still be used in a group
+    assert_eq!(parse_to_ast("[1 ? 2 : 3, (4, 5)]"), "(list (?: 1 2 3) (group (, 4 5)))");
+    assert_eq!(parse_errors("[1, 2"), vec!["[line 1] Error at end: Expect ']' after list elements."]);
+    assert_eq!(parse_errors("[1,]"), vec!["[line 1] Error at ']': Expect expression."]);
+}
+
+#[test]
+fn index_test() {
+    assert_eq!(parse_to_ast("[1, 2][0]"), "([] (list 1 2) 0)");
+    assert_eq!(parse_to_ast("-[[1]][0][1 + 2]"), "(- ([] ([] (list (list 1)) 0) (+ 1 2)))");
+    assert_eq!(parse_errors("[1][0"), vec!["[line 1] Error at end: Expect ']' after index."]);
+}
+
+#[test]
+fn index_assign_test() {
+    assert_eq!(parse_to_ast("[1][0] = 2"), "([]= (list 1) 0 2)");
+    // Right-associative, and looser than anything but the comma
+    assert_eq!(parse_to_ast("[1][0] = [2][0] = 1 + 2, 3"), "(, ([]= (list 1) 0 ([]= (list 2) 0 (+ 1 2))) 3)");
+    assert_eq!(parse_errors("1 = 2"), vec!["[line 1] Error at '=': Invalid assignment target."]);
+    assert_eq!(parse_errors("1 + [1][0] = 2"), vec!["[line 1] Error at '=': Invalid assignment target."]);
+    assert_eq!(parse_errors("([1][0]) = 2"), vec!["[line 1] Error at '=': Invalid assignment target."]);
+}
+
+#[test]
+fn tolerant_invalid_assignment_target_test() {
+    let error = String::from("[line 1] Error at '=': Invalid assignment target.");
+    assert_eq!(parse_tolerant("1 = [1, 2, 3]"), (String::from("(error 1 = [ 1 , 2 , 3 ])"), vec![error.clone()]));
+    assert_eq!(parse_tolerant("{1:2}[0] = 1 = 2"), (String::from("([]= (map 1 2) 0 (error 1 = 2))"), vec![error]));
+}
+
+#[test]
+fn tolerant_lists_test() {
+    assert_eq!(
+        parse_tolerant("[1, ][0"),
+        (
+            String::from("([] (list 1 <missing>) 0)"),
+            vec![
+                String::from("[line 1] Error at ']': Expect expression."),
+                String::from("[line 1] Error at end: Expect ']' after index."),
+            ]
+        )
+    );
+}
+
+#[test]
+fn map_test() {
+    assert_eq!(parse_to_ast("{}"), "(map)");
+    assert_eq!(parse_to_ast("{\"a\": 1, 2: [true], nil: {}}"), "(map a 1 2 (list true) nil (map))");
+    // The ':' of a conditional key belongs to the conditional
+    assert_eq!(parse_to_ast("{1 ? 2 : 3: 4}"), "(map (?: 1 2 3) 4)");
+    assert_eq!(parse_to_ast("{\"a\": 1}[\"a\"] = 2"), "([]= (map a 1) a 2)");
+    assert_eq!(parse_errors("{1}"), vec!["[line 1] Error at '}': Expect ':' after map key."]);
+    assert_eq!(parse_errors("{1: 2"), vec!["[line 1] Error at end: Expect '}' after map entries."]);
+}
+
+#[test]
+fn tolerant_maps_test() {
+    assert_eq!(
+        parse_tolerant("{1, 2: }"),
+        (
+            String::from("(map 1 <missing> 2 <missing>)"),
+            vec![
+                String::from("[line 1] Error at ',': Expect ':' after map key."),
+                String::from("[line 1] Error at '}': Expect expression."),
+            ]
+        )
+    );
+}
+
+#[test]
+fn range_test() {
+    assert_eq!(parse_to_ast("1..10"), "(.. 1 10)");
+    assert_eq!(parse_to_ast("0 + 1..=2 * 5 == -1..3"), "(== (..= (+ 0 1) (* 2 5)) (.. (- 1) 3))");
+    assert_eq!(parse_to_ast("(1..2)..3"), "(.. (group (.. 1 2)) 3)");
+    assert_eq!(parse_errors("1..2..3"), vec!["[line 1] Error at '..': Can't chain '..' without parentheses."]);
+    assert_eq!(parse_errors("1..2..=3"), vec!["[line 1] Error at '..=': Can't chain '..=' without parentheses."]);
+}
+
+#[test]
+fn arithmetic_operators_test() {
+    assert_eq!(parse_to_ast("7 % 3 * 2 ~/ 4 + 1"), "(+ (~/ (* (% 7 3) 2) 4) 1)");
+    assert_eq!(parse_to_ast("2 ** 3 ** 2"), "(** 2 (** 3 2))");
+    assert_eq!(parse_to_ast("-2 ** 2"), "(- (** 2 2))");
+    assert_eq!(parse_to_ast("2 ** -1 * 3"), "(* (** 2 (- 1)) 3)");
+    assert_eq!(parse_to_ast("[2][0] ** 2"), "(** ([] (list 2) 0) 2)");
+}
+
+#[test]
+fn rpn_exponent_test() {
+    let parser = Parser::new(scan("-2 ** 3 ** 2 % 5"));
+    assert_eq!(RpnPrinter.print(&parser.parse().unwrap()), "2 3 2 ** ** - 5 %");
+}
+
+#[test]
+fn bitwise_operators_test() {
+    assert_eq!(parse_to_ast("1 | 2 ^ 3 & 4 << 5 + 6"), "(| 1 (^ 2 (& 3 (<< 4 (+ 5 6)))))");
+    assert_eq!(parse_to_ast("8 >> 1 << 2"), "(<< (>> 8 1) 2)");
+    assert_eq!(parse_to_ast("7 & 4 == 0"), "(== (& 7 4) 0)");
+    assert_eq!(parse_to_ast("~-1 & ~2 ** 3"), "(& (~ (- 1)) (~ (** 2 3)))");
+    assert_eq!(parse_to_ast("0 .. 1 << 4"), "(.. 0 (<< 1 4))");
+}
+
+#[test]
+fn compound_assign_test() {
+    assert_eq!(parse_to_ast("[1][0] += 2 * 3"), "([]+= (list 1) 0 (* 2 3))");
+    assert_eq!(parse_to_ast("[1][0] -= [2][0] %= 3"), "([]-= (list 1) 0 ([]%= (list 2) 0 3))");
+    assert_eq!(parse_to_ast("[1][0] *= 2, [1][0] /= 2"), "(, ([]*= (list 1) 0 2) ([]/= (list 1) 0 2))");
+    assert_eq!(parse_errors("1 += 2"), vec!["[line 1] Error at '+=': Invalid assignment target."]);
+}
+
+#[test]
+fn increment_test() {
+    assert_eq!(parse_to_ast("[1][0]++"), "(([] (list 1) 0) ++)");
+    assert_eq!(parse_to_ast("--[1][0] ** 2"), "(** (-- ([] (list 1) 0)) 2)");
+    assert_eq!(parse_to_ast("-[1][0]-- - 1"), "(- (- (([] (list 1) 0) --)) 1)");
+    assert_eq!(parse_to_ast("1 - -1"), "(- 1 (- 1))");
+    assert_eq!(parse_errors("1++"), vec!["[line 1] Error at '++': Invalid assignment target."]);
+    assert_eq!(parse_errors("--(1)"), vec!["[line 1] Error at '--': Invalid assignment target."]);
+    assert_eq!(parse_errors("++[1][0]++"), vec!["[line 1] Error at '++': Invalid assignment target."]);
+}
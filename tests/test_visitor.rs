@@ -0,0 +1,69 @@
+mod common;
+
+use common::parse;
+use yarli::lexer::{Literal, LiteralValue};
+use yarli::parser::{AstPrinter, Expr, ExprKind, NodeId};
+use yarli::visitor::{walk_expr, walk_expr_mut, Visitor, VisitorMut};
+
+// Only cares about literals, everything else is walked by the defaults.
+struct LiteralCounter {
+    count: usize,
+}
+
+impl Visitor for LiteralCounter {
+    fn visit_literal(&mut self, _value: &Literal) {
+        self.count += 1;
+    }
+}
+
+#[test]
+fn default_walk_reaches_every_literal_test() {
+    let mut counter = LiteralCounter { count: 0 };
+    counter.visit_expr(&parse("1 ? (2, 3) : -4 == nil"));
+    assert_eq!(counter.count, 5);
+}
+
+// Records nodes by id, the way a side table would be filled.
+struct IdCollector {
+    ids: Vec<NodeId>,
+}
+
+impl Visitor for IdCollector {
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.ids.push(expr.id);
+        walk_expr(self, expr);
+    }
+}
+
+#[test]
+fn visit_expr_sees_every_node_test() {
+    let mut collector = IdCollector { ids: Vec::new() };
+    collector.visit_expr(&parse("-(1 + 2)"));
+    assert_eq!(collector.ids.len(), 5);
+}
+
+// Folds groupings away by replacing them with the expression they contain.
+struct UngroupPass;
+
+impl VisitorMut for UngroupPass {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+        if let ExprKind::Grouping { expression } = &mut expr.kind {
+            let inner = std::mem::replace(expression.as_mut(), Expr { id: expr.id, span: expr.span, kind: ExprKind::Literal { value: None } });
+            *expr = inner;
+        }
+    }
+
+    fn visit_literal_mut(&mut self, value: &mut Literal) {
+        if let Some(LiteralValue::Number(number)) = value {
+            *number *= 2.0;
+        }
+    }
+}
+
+#[test]
+fn mutable_visitor_rewrites_tree_test() {
+    let mut expr = parse("((1) + 2) * (3)");
+    UngroupPass.visit_expr_mut(&mut expr);
+    assert_eq!(AstPrinter.print(&expr), "(* (+ 2 4) 6)");
+}